@@ -0,0 +1,605 @@
+//! In-memory stand-in for the chat server, for running the TUI without the real
+//! backend:
+//!
+//!     cargo run --example mock_server -- [--port 8080] [--no-ws] [--session-ttl <secs>]
+//!
+//! `--no-ws` leaves out the WebSocket endpoint so the client's long-poll
+//! fallback can be exercised. `--session-ttl` makes logins expire, to try out
+//! what the client does when its session is turned away.
+
+use axum::{
+    extract::{
+        ws::{Message as WsMessage, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeSet, HashMap},
+    env,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::{broadcast, Notify};
+
+/// Longest message `send_message` and `send_direct_message` accept.
+const MAX_MESSAGE_LENGTH: usize = 2000;
+/// How long a "since id" request is held open waiting for new messages.
+const LONG_POLL: Duration = Duration::from_secs(25);
+
+#[derive(Default)]
+struct Db {
+    users: HashMap<String, String>,
+    rooms: Vec<Value>,
+    room_members: HashMap<i64, BTreeSet<String>>,
+    messages: Vec<Value>,
+    direct_messages: Vec<Value>,
+    friends: HashMap<String, BTreeSet<String>>,
+    friend_requests: Vec<Value>,
+    /// Session tokens handed out by `login`, with who they belong to and when.
+    sessions: HashMap<String, (String, Instant)>,
+    next_id: i64,
+}
+
+impl Db {
+    fn next_id(&mut self) -> i64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Starts a session for `username` and returns the cookie that carries it.
+    fn start_session(&mut self, username: &str) -> String {
+        let token = format!(
+            "{:x}{:x}",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+            self.next_id()
+        );
+        self.sessions
+            .insert(token.clone(), (username.to_string(), Instant::now()));
+        format!("auth-token={token}; Path=/; HttpOnly")
+    }
+}
+
+#[derive(Clone)]
+struct AppState {
+    db: Arc<Mutex<Db>>,
+    /// Frames for connected WebSockets, with the users allowed to see them
+    /// (`None` for everyone).
+    push: broadcast::Sender<(Option<Vec<String>>, String)>,
+    /// Wakes long-polling requests when something new arrives.
+    activity: Arc<Notify>,
+    /// How long a login lasts; forever when not set.
+    session_ttl: Option<Duration>,
+}
+
+impl AppState {
+    /// Tells WebSockets and waiting long polls about a new record.
+    fn publish(&self, audience: Option<Vec<String>>, kind: &str, data: Value) {
+        let frame = json!({ "type": kind, "data": data }).to_string();
+        let _ = self.push.send((audience, frame));
+        self.activity.notify_waiters();
+    }
+
+    /// The logged in user, looked up from the cookie `login` sets.
+    fn session(&self, headers: &HeaderMap) -> Option<String> {
+        let token = headers
+            .get(header::COOKIE)?
+            .to_str()
+            .ok()?
+            .split(';')
+            .find_map(|cookie| cookie.trim().strip_prefix("auth-token="))?;
+        let mut db = self.db.lock().unwrap();
+        let (user, started) = db.sessions.get(token)?.clone();
+        if self.session_ttl.is_some_and(|ttl| started.elapsed() > ttl) {
+            db.sessions.remove(token);
+            return None;
+        }
+        Some(user)
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let mut port = 8080;
+    let mut websocket = true;
+    let mut session_ttl = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => port = args.next().and_then(|p| p.parse().ok()).unwrap_or(port),
+            "--no-ws" => websocket = false,
+            "--session-ttl" => {
+                session_ttl = args
+                    .next()
+                    .and_then(|secs| secs.parse().ok())
+                    .map(Duration::from_secs)
+            }
+            other => eprintln!("ignoring unknown argument {other}"),
+        }
+    }
+
+    let mut db = Db::default();
+    for title in ["General Chat", "School Chat"] {
+        let id = db.next_id();
+        db.rooms.push(json!({ "id": id, "title": title }));
+    }
+    // A little history from earlier days, so there is something to scroll back to
+    for (days_ago, text) in [(3, "Welcome to General Chat!"), (1, "Anyone around?")] {
+        let id = db.next_id();
+        let sent = Utc::now() - chrono::Duration::days(days_ago);
+        db.messages.push(json!({
+            "id": id,
+            "message_text": text,
+            "message_room_id": 1,
+            "message_user_name": "crimpz",
+            "created_at": sent.to_rfc3339(),
+        }));
+    }
+
+    let state = AppState {
+        db: Arc::new(Mutex::new(db)),
+        push: broadcast::channel(64).0,
+        activity: Arc::new(Notify::new()),
+        session_ttl,
+    };
+
+    let mut app = Router::new()
+        .route("/api/create_user", post(create_user))
+        .route("/api/login", post(login))
+        .route("/api/rpc", post(rpc));
+    if websocket {
+        app = app.route("/api/ws", get(ws));
+    }
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    println!("mock server listening on http://{addr} (websocket: {websocket})");
+    axum::Server::bind(&addr)
+        .serve(app.with_state(state).into_make_service())
+        .await
+        .unwrap();
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": { "message": message } }))).into_response()
+}
+
+async fn create_user(State(state): State<AppState>, Json(body): Json<Value>) -> Response {
+    let (Some(username), Some(pwd)) = (body["username"].as_str(), body["pwd"].as_str()) else {
+        return error(StatusCode::BAD_REQUEST, "username and pwd are required");
+    };
+
+    let mut db = state.db.lock().unwrap();
+    if db.users.contains_key(username) {
+        return error(StatusCode::CONFLICT, "Username is already taken");
+    }
+    db.users.insert(username.to_string(), pwd.to_string());
+    // New accounts start out in the rooms that were there from the beginning
+    for room_id in [1, 2] {
+        db.room_members
+            .entry(room_id)
+            .or_default()
+            .insert(username.to_string());
+    }
+    Json(json!({ "result": { "success": true } })).into_response()
+}
+
+async fn login(State(state): State<AppState>, Json(body): Json<Value>) -> Response {
+    let username = body["username"].as_str().unwrap_or_default();
+    let pwd = body["pwd"].as_str().unwrap_or_default();
+
+    let mut db = state.db.lock().unwrap();
+    if db.users.get(username).map(String::as_str) != Some(pwd) {
+        return error(StatusCode::FORBIDDEN, "Wrong username or password");
+    }
+
+    (
+        [(header::SET_COOKIE, db.start_session(username))],
+        Json(json!({ "result": { "success": true } })),
+    )
+        .into_response()
+}
+
+async fn rpc(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<Value>,
+) -> Response {
+    let Some(user) = state.session(&headers) else {
+        return error(StatusCode::UNAUTHORIZED, "Not logged in");
+    };
+    let id = req["id"].clone();
+    let params = &req["params"];
+
+    let too_long = params["data"]["message_text"]
+        .as_str()
+        .is_some_and(|text| text.chars().count() > MAX_MESSAGE_LENGTH);
+
+    let result = match req["method"].as_str().unwrap_or_default() {
+        "send_message" | "send_direct_message" if too_long => Err(format!(
+            "Messages can be at most {MAX_MESSAGE_LENGTH} characters"
+        )),
+
+        "get_server_info" => Ok(json!({ "max_message_length": MAX_MESSAGE_LENGTH })),
+
+        "list_rooms" => {
+            let db = state.db.lock().unwrap();
+            let rooms: Vec<Value> = db
+                .rooms
+                .iter()
+                .map(|room| {
+                    let joined = room["id"]
+                        .as_i64()
+                        .and_then(|id| db.room_members.get(&id))
+                        .is_some_and(|members| members.contains(&user));
+                    json!({
+                        "id": room["id"],
+                        "title": room["title"],
+                        "topic": room["topic"],
+                        "joined": joined,
+                    })
+                })
+                .collect();
+            Ok(json!(rooms))
+        }
+
+        "create_room" => {
+            let title = params["title"].as_str().unwrap_or_default().trim();
+            if title.is_empty() {
+                Err("A room needs a title".to_string())
+            } else {
+                let mut db = state.db.lock().unwrap();
+                let id = db.next_id();
+                db.rooms.push(json!({ "id": id, "title": title }));
+                db.room_members.entry(id).or_default().insert(user.clone());
+                drop(db);
+                state.publish(None, "rooms_changed", Value::Null);
+                Ok(json!({ "id": id, "title": title, "joined": true }))
+            }
+        }
+
+        "rename_room" => {
+            let title = params["title"].as_str().unwrap_or_default().trim();
+            let mut db = state.db.lock().unwrap();
+            let is_member = params["id"]
+                .as_i64()
+                .and_then(|id| db.room_members.get(&id))
+                .is_some_and(|members| members.contains(&user));
+            match db.rooms.iter_mut().find(|room| room["id"] == params["id"]) {
+                None => Err("No such room".to_string()),
+                Some(_) if !is_member => Err("Only members can rename a room".to_string()),
+                Some(_) if title.is_empty() => Err("A room needs a title".to_string()),
+                Some(room) => {
+                    room["title"] = json!(title);
+                    drop(db);
+                    state.publish(None, "rooms_changed", Value::Null);
+                    Ok(Value::Null)
+                }
+            }
+        }
+
+        "set_room_topic" => {
+            let topic = params["topic"].as_str().unwrap_or_default().trim();
+            let mut db = state.db.lock().unwrap();
+            match db.rooms.iter_mut().find(|room| room["id"] == params["id"]) {
+                None => Err("No such room".to_string()),
+                Some(room) => {
+                    room["topic"] = json!(topic);
+                    drop(db);
+                    state.publish(None, "rooms_changed", Value::Null);
+                    Ok(Value::Null)
+                }
+            }
+        }
+
+        "join_room" | "leave_room" => {
+            let join = req["method"] == "join_room";
+            let mut db = state.db.lock().unwrap();
+            match params["id"].as_i64() {
+                Some(id) if db.rooms.iter().any(|room| room["id"] == id) => {
+                    let members = db.room_members.entry(id).or_default();
+                    if join {
+                        members.insert(user.clone());
+                    } else {
+                        members.remove(&user);
+                    }
+                    drop(db);
+                    state.publish(Some(vec![user.clone()]), "rooms_changed", Value::Null);
+                    Ok(Value::Null)
+                }
+                _ => Err("No such room".to_string()),
+            }
+        }
+
+        "get_messages_by_room_id" => {
+            // Either {room_id, before_id?, since_id?, limit} for a page of
+            // history, {room_id, since_id} for only what's newer (held open
+            // until something arrives), or a bare room id for everything
+            let room_id = params["room_id"].as_i64().or(params.as_i64());
+            let since_id = params["since_id"].as_i64();
+            let before_id = params["before_id"].as_i64();
+            let limit = params["limit"].as_u64().map(|limit| limit as usize);
+            let deadline = tokio::time::Instant::now() + LONG_POLL;
+            loop {
+                let notified = state.activity.notified();
+                let found: Vec<Value> = state
+                    .db
+                    .lock()
+                    .unwrap()
+                    .messages
+                    .iter()
+                    .filter(|m| m["message_room_id"].as_i64() == room_id)
+                    .filter(|m| since_id.is_none_or(|since| m["id"].as_i64() > Some(since)))
+                    .filter(|m| before_id.is_none_or(|before| m["id"].as_i64() < Some(before)))
+                    .cloned()
+                    .collect();
+                if let Some(limit) = limit {
+                    let skip = found.len().saturating_sub(limit);
+                    break Ok(json!(found[skip..]));
+                }
+                if !found.is_empty() || since_id.is_none() {
+                    break Ok(json!(found));
+                }
+                if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                    break Ok(json!([]));
+                }
+            }
+        }
+
+        "send_message" => {
+            let data = &params["data"];
+            let is_member = data["message_room_id"]
+                .as_i64()
+                .and_then(|id| state.db.lock().unwrap().room_members.get(&id).cloned())
+                .is_some_and(|members| members.contains(&user));
+            if !is_member {
+                return Json(json!({
+                    "id": id,
+                    "error": { "code": -32000, "message": "Join the room before posting" },
+                }))
+                .into_response();
+            }
+            let message = {
+                let mut db = state.db.lock().unwrap();
+                // A retry of something already posted gets the original back
+                let repeat = db.messages.iter().find(|m| {
+                    m["message_user_name"] == user
+                        && !data["client_id"].is_null()
+                        && m["client_id"] == data["client_id"]
+                });
+                if let Some(message) = repeat {
+                    return Json(json!({ "id": id, "result": message })).into_response();
+                }
+                let message = json!({
+                    "id": db.next_id(),
+                    "client_id": data["client_id"],
+                    "message_text": data["message_text"],
+                    "message_room_id": data["message_room_id"],
+                    "message_user_name": user,
+                    "created_at": Utc::now().to_rfc3339(),
+                });
+                db.messages.push(message.clone());
+                message
+            };
+            state.publish(None, "message", message.clone());
+            Ok(message)
+        }
+
+        "get_direct_messages" => {
+            let friend = params["username"].as_str().unwrap_or_default().to_string();
+            let since_id = params["since_id"].as_i64();
+            let between = |m: &Value| {
+                let (from, to) = (&m["sender_user_name"], &m["recipient_user_name"]);
+                (from == &user && to == &friend) || (from == &friend && to == &user)
+            };
+            let deadline = tokio::time::Instant::now() + LONG_POLL;
+            loop {
+                let notified = state.activity.notified();
+                let found: Vec<Value> = state
+                    .db
+                    .lock()
+                    .unwrap()
+                    .direct_messages
+                    .iter()
+                    .filter(|m| between(m))
+                    .filter(|m| since_id.is_none_or(|since| m["id"].as_i64() > Some(since)))
+                    .cloned()
+                    .collect();
+                if !found.is_empty() || since_id.is_none() {
+                    break Ok(json!(found));
+                }
+                if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                    break Ok(json!([]));
+                }
+            }
+        }
+
+        "send_direct_message" => {
+            let data = &params["data"];
+            let recipient = data["recipient_user_name"].as_str().unwrap_or_default();
+            let message = {
+                let mut db = state.db.lock().unwrap();
+                let repeat = db.direct_messages.iter().find(|m| {
+                    m["sender_user_name"] == user
+                        && !data["client_id"].is_null()
+                        && m["client_id"] == data["client_id"]
+                });
+                if let Some(message) = repeat {
+                    return Json(json!({ "id": id, "result": message })).into_response();
+                }
+                let message = json!({
+                    "id": db.next_id(),
+                    "client_id": data["client_id"],
+                    "message_text": data["message_text"],
+                    "sender_user_name": user,
+                    "recipient_user_name": recipient,
+                    "created_at": Utc::now().to_rfc3339(),
+                });
+                db.direct_messages.push(message.clone());
+                message
+            };
+            state.publish(
+                Some(vec![user.clone(), recipient.to_string()]),
+                "direct_message",
+                message.clone(),
+            );
+            Ok(message)
+        }
+
+        "rename_user" => {
+            let name = params["username"].as_str().unwrap_or_default().to_string();
+            let mut db = state.db.lock().unwrap();
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                Err("That isn't a valid username".to_string())
+            } else if db.users.contains_key(&name) {
+                Err(format!("{name} is already taken"))
+            } else {
+                let pwd = db.users.remove(&user).unwrap_or_default();
+                db.users.insert(name.clone(), pwd);
+                for members in db.room_members.values_mut() {
+                    if members.remove(&user) {
+                        members.insert(name.clone());
+                    }
+                }
+                if let Some(friends) = db.friends.remove(&user) {
+                    db.friends.insert(name.clone(), friends);
+                }
+                for friends in db.friends.values_mut() {
+                    if friends.remove(&user) {
+                        friends.insert(name.clone());
+                    }
+                }
+                for (owner, _) in db.sessions.values_mut() {
+                    if *owner == user {
+                        owner.clone_from(&name);
+                    }
+                }
+                Ok(Value::Null)
+            }
+        }
+
+        "list_friends" => {
+            let db = state.db.lock().unwrap();
+            Ok(json!(db.friends.get(&user).cloned().unwrap_or_default()))
+        }
+
+        "list_friend_requests" => {
+            let db = state.db.lock().unwrap();
+            let mine: Vec<&Value> = db
+                .friend_requests
+                .iter()
+                .filter(|r| r["from_user_name"] == user || r["to_user_name"] == user)
+                .collect();
+            Ok(json!(mine))
+        }
+
+        "send_friend_request" => {
+            let to = params["username"].as_str().unwrap_or_default().to_string();
+            let mut db = state.db.lock().unwrap();
+            if !db.users.contains_key(&to) || to == user {
+                Err(format!("No user named '{to}'"))
+            } else if db.friends.get(&user).is_some_and(|f| f.contains(&to)) {
+                Err(format!("{to} is already your friend"))
+            } else {
+                let id = db.next_id();
+                db.friend_requests.push(json!({
+                    "id": id,
+                    "from_user_name": user,
+                    "to_user_name": to,
+                }));
+                drop(db);
+                state.publish(Some(vec![user.clone(), to]), "friends_changed", Value::Null);
+                Ok(Value::Null)
+            }
+        }
+
+        "accept_friend_request" | "decline_friend_request" => {
+            let accept = req["method"] == "accept_friend_request";
+            let mut db = state.db.lock().unwrap();
+            let index = db
+                .friend_requests
+                .iter()
+                .position(|r| r["id"] == params["id"] && r["to_user_name"] == user);
+            match index {
+                None => Err("No such friend request".to_string()),
+                Some(index) => {
+                    let request = db.friend_requests.remove(index);
+                    let from = request["from_user_name"].as_str().unwrap().to_string();
+                    if accept {
+                        db.friends
+                            .entry(user.clone())
+                            .or_default()
+                            .insert(from.clone());
+                        db.friends
+                            .entry(from.clone())
+                            .or_default()
+                            .insert(user.clone());
+                    }
+                    drop(db);
+                    state.publish(
+                        Some(vec![user.clone(), from]),
+                        "friends_changed",
+                        Value::Null,
+                    );
+                    Ok(Value::Null)
+                }
+            }
+        }
+
+        "remove_friend" => {
+            let other = params["username"].as_str().unwrap_or_default().to_string();
+            let mut db = state.db.lock().unwrap();
+            db.friends.entry(user.clone()).or_default().remove(&other);
+            db.friends.entry(other.clone()).or_default().remove(&user);
+            drop(db);
+            state.publish(
+                Some(vec![user.clone(), other]),
+                "friends_changed",
+                Value::Null,
+            );
+            Ok(Value::Null)
+        }
+
+        method => Err(format!("Unknown method '{method}'")),
+    };
+
+    match result {
+        Ok(result) => Json(json!({ "id": id, "result": result })).into_response(),
+        Err(message) => Json(json!({
+            "id": id,
+            "error": { "code": -32601, "message": message },
+        }))
+        .into_response(),
+    }
+}
+
+async fn ws(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let Some(user) = state.session(&headers) else {
+        return error(StatusCode::UNAUTHORIZED, "Not logged in");
+    };
+    upgrade.on_upgrade(move |socket| forward(socket, user, state.push.subscribe()))
+}
+
+async fn forward(
+    mut socket: WebSocket,
+    user: String,
+    mut frames: broadcast::Receiver<(Option<Vec<String>>, String)>,
+) {
+    while let Ok((audience, frame)) = frames.recv().await {
+        if audience.is_some_and(|audience| !audience.contains(&user)) {
+            continue;
+        }
+        if socket.send(WsMessage::Text(frame)).await.is_err() {
+            break;
+        }
+    }
+}
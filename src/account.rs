@@ -0,0 +1,58 @@
+use crate::cache::MessageCache;
+use crate::completion::Completion;
+use crate::interact::{DirectMessage, FriendRequest, Message, Room};
+use crate::outbox::Outbox;
+use crate::read_state::ReadState;
+use crate::structs::{Prompt, Scrollback};
+use std::collections::{HashMap, HashSet};
+
+/// Everything shown or kept for whoever is logged in. Replaced wholesale
+/// with `Account::default()` whenever someone else takes over, so nothing
+/// of one account can turn up under another.
+#[derive(Default)]
+pub struct Account {
+    pub chat_rooms: Vec<Room>,
+    pub chat: Vec<Message>,
+    // Last failure loading or sending, shown in place of the list it affected
+    pub rooms_error: Option<String>,
+    pub chat_error: Option<String>,
+    /// How far back each room was scrolled.
+    pub scrollbacks: HashMap<i64, Scrollback>,
+    /// Tracked by room rather than list position so it survives the list
+    /// being refreshed.
+    pub selected_room: Option<Room>,
+
+    pub friends: Vec<String>,
+    pub selected_friend: Option<String>,
+    pub dm_chat: Vec<DirectMessage>,
+    pub dm_error: Option<String>,
+    pub friend_requests: Vec<FriendRequest>,
+    pub selected_request: Option<i64>,
+    pub confirm_remove: bool,
+    pub friends_error: Option<String>,
+
+    /// Highlighted messages from every room, oldest first.
+    pub mentions: Vec<Message>,
+    pub selected_mention: Option<i64>,
+    /// Joined rooms whose latest page has been checked for mentions.
+    pub scanned_rooms: HashSet<i64>,
+    /// Friends whose private messages have been checked for unread ones.
+    pub scanned_friends: HashSet<String>,
+    /// A message picked on the Mentions tab, waiting for its room to load.
+    pub jump_to: Option<i64>,
+
+    pub read_state: ReadState,
+    pub cache: MessageCache,
+    pub outbox: Outbox,
+    /// What had been read in the open room or private chat when it was
+    /// opened, which is where the "New messages" divider goes.
+    pub room_divider: Option<(i64, Option<i64>)>,
+    pub dm_divider: Option<(String, Option<i64>)>,
+
+    /// Tab completion being cycled through in the input box.
+    pub completion: Option<Completion>,
+    /// Set while the input box is collecting something other than a message.
+    pub prompt: Option<Prompt>,
+    /// Advertised by the server after login; None means it didn't say.
+    pub max_message_length: Option<usize>,
+}
@@ -0,0 +1,70 @@
+/// Whether `text` mentions `username` or one of `keywords`, ignoring case.
+/// Only whole words count, so "al" doesn't match "also" but does "@al".
+pub fn is_highlighted(text: &str, username: &str, keywords: &[String]) -> bool {
+    let text = text.to_lowercase();
+    std::iter::once(username)
+        .chain(keywords.iter().map(String::as_str))
+        .filter(|word| !word.trim().is_empty())
+        .any(|word| contains_word(&text, &word.to_lowercase()))
+}
+
+fn contains_word(text: &str, word: &str) -> bool {
+    let is_word_char = |ch: char| ch.is_alphanumeric() || ch == '_';
+    text.match_indices(word).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + word.len()..].chars().next();
+        !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whole_words_only() {
+        assert!(contains_word("hi al", "al"));
+        assert!(contains_word("al, you there?", "al"));
+        assert!(!contains_word("also", "al"));
+        assert!(!contains_word("halal", "al"));
+        assert!(!contains_word("al_bot", "al"));
+        assert!(!contains_word("al2", "al"));
+    }
+
+    #[test]
+    fn punctuation_is_a_boundary() {
+        assert!(contains_word("@al", "al"));
+        assert!(contains_word("(al)", "al"));
+        assert!(contains_word("al's turn", "al"));
+        assert!(contains_word("deploy-bot failed", "deploy"));
+    }
+
+    #[test]
+    fn later_matches_count_when_the_first_is_inside_a_word() {
+        assert!(contains_word("also al", "al"));
+    }
+
+    #[test]
+    fn letters_beyond_ascii_are_word_characters() {
+        assert!(!contains_word("álex", "lex"));
+        assert!(contains_word("¿alex?", "alex"));
+    }
+
+    #[test]
+    fn highlights_ignore_case() {
+        let keywords = ["Deploy".to_string()];
+        assert!(is_highlighted("Hey ALICE", "alice", &[]));
+        assert!(is_highlighted("deploy is done", "alice", &keywords));
+        assert!(!is_highlighted("alicea redeployed", "alice", &keywords));
+    }
+
+    #[test]
+    fn blank_keywords_match_nothing() {
+        assert!(!is_highlighted(
+            "anything at all",
+            "bob",
+            &[" ".to_string()]
+        ));
+        assert!(!is_highlighted("anything at all", "", &[]));
+    }
+}
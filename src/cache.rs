@@ -0,0 +1,143 @@
+use crate::interact::{Message, Room};
+use crate::structs::Error;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Room history and the room list as last seen, kept on disk for each
+/// account so they show straight away and can be read without the server.
+///
+/// Each room's history is an append-only log of JSON lines; when a message
+/// turns up more than once the last copy wins. Only pages fetched from the
+/// server go in, never pushed messages, so a log has no gaps for syncing
+/// from its newest message to skip over.
+#[derive(Default)]
+pub struct MessageCache {
+    dir: Option<PathBuf>,
+    /// Ids already in each room's log, read in the first time it's opened.
+    stored: HashMap<i64, HashSet<i64>>,
+    /// The last thing that went wrong, for the status line.
+    error: Option<Error>,
+}
+
+impl MessageCache {
+    pub fn open(dir: PathBuf) -> MessageCache {
+        MessageCache {
+            dir: Some(dir),
+            ..MessageCache::default()
+        }
+    }
+
+    /// Whether anything has been cached in `dir`, as when this account has
+    /// logged in here before.
+    pub fn exists(dir: &Path) -> bool {
+        dir.join("rooms.json").exists()
+    }
+
+    pub fn rooms(&mut self) -> Vec<Room> {
+        let Some(path) = self.path("rooms.json").filter(|path| path.exists()) else {
+            return Vec::new();
+        };
+        let rooms = fs::read_to_string(path)
+            .map_err(Error::from)
+            .and_then(|content| Ok(serde_json::from_str(&content)?));
+        self.report(rooms).unwrap_or_default()
+    }
+
+    pub fn set_rooms(&mut self, rooms: &[Room]) {
+        let Some(path) = self.path("rooms.json") else {
+            return;
+        };
+        let written = serde_json::to_string(rooms)
+            .map_err(Error::from)
+            .and_then(|json| Ok(write(&path, json.as_bytes(), false)?));
+        self.report(written);
+    }
+
+    /// A room's cached history, oldest first.
+    pub fn messages(&mut self, room_id: i64) -> Vec<Message> {
+        let Some(path) = self.path(&format!("rooms/{room_id}.jsonl")) else {
+            return Vec::new();
+        };
+        // A room that's never been opened has no log yet
+        let content = fs::read_to_string(path).unwrap_or_default();
+
+        let mut by_id = HashMap::new();
+        for line in content.lines() {
+            // A line cut short by a crash loses one message, not the room
+            if let Ok(message) = serde_json::from_str::<Message>(line) {
+                by_id.insert(message.id, message);
+            }
+        }
+        self.stored.insert(room_id, by_id.keys().copied().collect());
+
+        let mut messages: Vec<Message> = by_id.into_values().collect();
+        messages.sort_by_key(|message| message.id);
+        messages
+    }
+
+    /// The newest message cached for a room.
+    pub fn newest(&mut self, room_id: i64) -> Option<i64> {
+        if !self.stored.contains_key(&room_id) {
+            self.messages(room_id);
+        }
+        self.stored.get(&room_id)?.iter().max().copied()
+    }
+
+    /// Adds whichever of `messages` aren't in the room's log yet.
+    pub fn add(&mut self, room_id: i64, messages: &[Message]) {
+        let Some(path) = self.path(&format!("rooms/{room_id}.jsonl")) else {
+            return;
+        };
+        if !self.stored.contains_key(&room_id) {
+            self.messages(room_id);
+        }
+        let stored = self.stored.entry(room_id).or_default();
+
+        let mut lines = String::new();
+        for message in messages {
+            if stored.insert(message.id) {
+                if let Ok(json) = serde_json::to_string(message) {
+                    lines.push_str(&json);
+                    lines.push('\n');
+                }
+            }
+        }
+        if lines.is_empty() {
+            return;
+        }
+
+        let written = write(&path, lines.as_bytes(), true);
+        self.report(written.map_err(Error::from));
+    }
+
+    /// Hands over what last went wrong, once.
+    pub fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+
+    fn path(&self, name: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(name))
+    }
+
+    fn report<T>(&mut self, result: Result<T, Error>) -> Option<T> {
+        result.map_err(|err| self.error = Some(err)).ok()
+    }
+}
+
+/// Writes `contents` to `path`, making its directory if need be.
+fn write(path: &Path, contents: &[u8], append: bool) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)?
+        .write_all(contents)
+}
@@ -0,0 +1,277 @@
+/// What a command expects after its name.
+#[derive(Copy, Clone)]
+enum Arity {
+    Nothing,
+    Word,
+    OptionalWord,
+    /// The rest of the line, spaces and all.
+    Text,
+    /// One word, then the rest of the line.
+    WordThenText,
+}
+
+/// One entry in the registry of `/` commands.
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub about: &'static str,
+    arity: Arity,
+}
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "join",
+        usage: "/join <room>",
+        about: "Join a room by name or id, creating it if there's none",
+        arity: Arity::Text,
+    },
+    CommandSpec {
+        name: "leave",
+        usage: "/leave",
+        about: "Leave the selected room",
+        arity: Arity::Nothing,
+    },
+    CommandSpec {
+        name: "msg",
+        usage: "/msg <friend> <message>",
+        about: "Send a private message to a friend",
+        arity: Arity::WordThenText,
+    },
+    CommandSpec {
+        name: "me",
+        usage: "/me <action>",
+        about: "Describe what you're doing, as in \"* you wave\"",
+        arity: Arity::Text,
+    },
+    CommandSpec {
+        name: "nick",
+        usage: "/nick <name>",
+        about: "Change your username",
+        arity: Arity::Word,
+    },
+    CommandSpec {
+        name: "topic",
+        usage: "/topic <topic>",
+        about: "Set the selected room's topic",
+        arity: Arity::Text,
+    },
+    CommandSpec {
+        name: "clear",
+        usage: "/clear",
+        about: "Clear the conversation on screen",
+        arity: Arity::Nothing,
+    },
+    CommandSpec {
+        name: "logout",
+        usage: "/logout",
+        about: "Log out, so the next start asks for a password",
+        arity: Arity::Nothing,
+    },
+    CommandSpec {
+        name: "quit",
+        usage: "/quit",
+        about: "Quit",
+        arity: Arity::Nothing,
+    },
+    CommandSpec {
+        name: "help",
+        usage: "/help [command]",
+        about: "List commands, or explain one",
+        arity: Arity::OptionalWord,
+    },
+];
+
+/// A command typed into the input box, with its arguments checked.
+#[derive(Debug, PartialEq)]
+pub enum SlashCommand {
+    Join(String),
+    Leave,
+    Msg { friend: String, text: String },
+    Me(String),
+    Nick(String),
+    Topic(String),
+    Clear,
+    Logout,
+    Quit,
+    Help(Option<String>),
+}
+
+/// Reads `input` as a command if it starts with `/`. Errors are meant for the
+/// status line. `//` escapes a message that really starts with a slash.
+pub fn parse(input: &str) -> Option<Result<SlashCommand, String>> {
+    let input = input.strip_prefix('/')?;
+    if input.starts_with('/') {
+        return None;
+    }
+
+    let (name, rest) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    let rest = rest.trim();
+    let Some(spec) = find(name) else {
+        return Some(Err(format!("Unknown command /{name}. Try /help")));
+    };
+    let usage = || format!("Usage: {}", spec.usage);
+
+    let args = match spec.arity {
+        Arity::Nothing if rest.is_empty() => Vec::new(),
+        Arity::Word if !rest.is_empty() && !rest.contains(char::is_whitespace) => vec![rest],
+        Arity::Text if !rest.is_empty() => vec![rest],
+        Arity::OptionalWord if !rest.contains(char::is_whitespace) => Some(rest)
+            .filter(|word| !word.is_empty())
+            .into_iter()
+            .collect(),
+        Arity::WordThenText => match rest.split_once(char::is_whitespace) {
+            Some((word, text)) if !text.trim().is_empty() => vec![word, text.trim()],
+            _ => return Some(Err(usage())),
+        },
+        _ => return Some(Err(usage())),
+    };
+
+    // The arity checks above guarantee the arguments each command reads
+    let arg = |index: usize| {
+        args.get(index)
+            .map(|arg| arg.to_string())
+            .unwrap_or_default()
+    };
+    Some(Ok(match spec.name {
+        "join" => SlashCommand::Join(arg(0)),
+        "leave" => SlashCommand::Leave,
+        "msg" => SlashCommand::Msg {
+            friend: arg(0),
+            text: arg(1),
+        },
+        "me" => SlashCommand::Me(arg(0)),
+        "nick" => SlashCommand::Nick(arg(0)),
+        "topic" => SlashCommand::Topic(arg(0)),
+        "clear" => SlashCommand::Clear,
+        "logout" => SlashCommand::Logout,
+        "quit" => SlashCommand::Quit,
+        _ => SlashCommand::Help(
+            args.first()
+                .map(|name| name.trim_start_matches('/').to_string()),
+        ),
+    }))
+}
+
+pub fn find(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| spec.name == name)
+}
+
+/// What `/help` shows: every command, or how to use one of them.
+pub fn help(name: Option<&str>) -> Result<String, String> {
+    match name {
+        None => {
+            let names: Vec<String> = COMMANDS
+                .iter()
+                .map(|spec| format!("/{}", spec.name))
+                .collect();
+            Ok(format!(
+                "Commands: {}. /help <command> for more",
+                names.join(" ")
+            ))
+        }
+        Some(name) => find(name)
+            .map(|spec| format!("{}: {}", spec.usage, spec.about))
+            .ok_or_else(|| format!("Unknown command /{name}. Try /help")),
+    }
+}
+
+/// Command names that start with `partial`, for Tab completion.
+pub fn names_starting_with(partial: &str) -> Vec<&'static str> {
+    COMMANDS
+        .iter()
+        .map(|spec| spec.name)
+        .filter(|name| name.starts_with(partial))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(input: &str) -> SlashCommand {
+        match parse(input) {
+            Some(Ok(command)) => command,
+            Some(Err(err)) => panic!("{input} was refused: {err}"),
+            None => panic!("{input} wasn't read as a command"),
+        }
+    }
+
+    fn refused(input: &str) -> String {
+        match parse(input) {
+            Some(Err(err)) => err,
+            Some(Ok(command)) => panic!("{input} was read as {command:?}"),
+            None => panic!("{input} wasn't read as a command"),
+        }
+    }
+
+    #[test]
+    fn messages_are_not_commands() {
+        assert!(parse("hello").is_none());
+        assert!(parse("").is_none());
+        // Doubling the slash sends it as it is
+        assert!(parse("//shrug").is_none());
+    }
+
+    #[test]
+    fn unknown_commands_are_refused() {
+        assert_eq!(
+            refused("/frobnicate"),
+            "Unknown command /frobnicate. Try /help"
+        );
+        assert_eq!(refused("/"), "Unknown command /. Try /help");
+        // Names are exact, not prefixes
+        assert_eq!(refused("/jo lobby"), "Unknown command /jo. Try /help");
+    }
+
+    #[test]
+    fn commands_without_arguments() {
+        assert_eq!(parsed("/leave"), SlashCommand::Leave);
+        assert_eq!(parsed("/quit  "), SlashCommand::Quit);
+        assert_eq!(refused("/clear everything"), "Usage: /clear");
+    }
+
+    #[test]
+    fn one_word_arguments() {
+        assert_eq!(parsed("/nick  bob "), SlashCommand::Nick("bob".to_string()));
+        assert_eq!(refused("/nick"), "Usage: /nick <name>");
+        assert_eq!(refused("/nick bob smith"), "Usage: /nick <name>");
+    }
+
+    #[test]
+    fn text_arguments_keep_their_spaces() {
+        assert_eq!(
+            parsed("/join  rust  beginners "),
+            SlashCommand::Join("rust  beginners".to_string())
+        );
+        assert_eq!(parsed("/me waves"), SlashCommand::Me("waves".to_string()));
+        assert_eq!(refused("/topic   "), "Usage: /topic <topic>");
+    }
+
+    #[test]
+    fn word_then_text() {
+        assert_eq!(
+            parsed("/msg alice see you  soon"),
+            SlashCommand::Msg {
+                friend: "alice".to_string(),
+                text: "see you  soon".to_string(),
+            }
+        );
+        assert_eq!(refused("/msg alice"), "Usage: /msg <friend> <message>");
+        assert_eq!(refused("/msg alice   "), "Usage: /msg <friend> <message>");
+        assert_eq!(refused("/msg"), "Usage: /msg <friend> <message>");
+    }
+
+    #[test]
+    fn help_takes_an_optional_command() {
+        assert_eq!(parsed("/help"), SlashCommand::Help(None));
+        assert_eq!(
+            parsed("/help /msg"),
+            SlashCommand::Help(Some("msg".to_string()))
+        );
+        assert_eq!(refused("/help msg me"), "Usage: /help [command]");
+        assert!(help(Some("nope")).is_err());
+        assert!(help(Some("msg"))
+            .unwrap()
+            .starts_with("/msg <friend> <message>"));
+    }
+}
@@ -0,0 +1,83 @@
+use crate::commands;
+
+/// Names Tab can complete from, gathered from what's on screen.
+pub struct Sources<'b> {
+    /// Everyone who has spoken in the open conversation.
+    pub participants: Vec<&'b str>,
+    pub friends: &'b [String],
+    pub rooms: Vec<&'b str>,
+}
+
+/// A Tab completion in progress: what matched and which one is in the input
+/// box, so the next Tab can swap it for the following one.
+pub struct Completion {
+    pub candidates: Vec<String>,
+    pub selected: usize,
+}
+
+impl Completion {
+    /// Finds what the word before the cursor could become. `line` is the text
+    /// up to the cursor. Returns how many characters to replace along with the
+    /// matches, or `None` if nothing matches.
+    pub fn start(line: &str, sources: &Sources) -> Option<(usize, Completion)> {
+        let word_start = line
+            .char_indices()
+            .rev()
+            .find(|(_, ch)| ch.is_whitespace())
+            .map_or(0, |(index, ch)| index + ch.len_utf8());
+        let (before, word) = line.split_at(word_start);
+        let friends = sources.friends.iter().map(String::as_str);
+        let rooms = sources.rooms.iter().copied();
+
+        let mut candidates = if before.is_empty() && word.starts_with('/') {
+            commands::names_starting_with(&word[1..])
+                .into_iter()
+                .map(|name| format!("/{name} "))
+                .collect()
+        } else if before == "/msg " {
+            matching(friends, word, "")
+        } else if before == "/join " {
+            matching(rooms, word, "")
+        } else if let Some(typed) = word.strip_prefix('@') {
+            let people = sources.participants.iter().copied().chain(friends);
+            matching(people, typed, "@")
+        } else if let Some(typed) = word.strip_prefix('#') {
+            matching(rooms, typed, "#")
+        } else {
+            Vec::new()
+        };
+
+        candidates.sort_by_key(|candidate| candidate.to_lowercase());
+        candidates.dedup();
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let completion = Completion {
+            candidates,
+            selected: 0,
+        };
+        Some((word.chars().count(), completion))
+    }
+
+    pub fn current(&self) -> &str {
+        &self.candidates[self.selected]
+    }
+
+    /// Moves on to the next match, wrapping around. Returns how many
+    /// characters of the previous one to replace.
+    pub fn advance(&mut self) -> usize {
+        let replaced = self.current().chars().count();
+        self.selected = (self.selected + 1) % self.candidates.len();
+        replaced
+    }
+}
+
+/// The names starting with `typed`, ignoring case, as they'd be inserted.
+fn matching<'n>(names: impl Iterator<Item = &'n str>, typed: &str, marker: &str) -> Vec<String> {
+    let typed = typed.to_lowercase();
+    names
+        .filter(|name| name.to_lowercase().starts_with(&typed))
+        .map(|name| format!("{marker}{name} "))
+        .collect()
+}
@@ -0,0 +1,420 @@
+use crate::structs::Error;
+use chrono::Local;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const APP_DIR: &str = "cli-chat";
+const DEFAULT_PROFILE: &str = "local";
+
+const USAGE: &str = "Usage: tui [--config <path>] [--server <url|profile>] [--profile <name>]
+
+  --config <path>     Read settings from <path> instead of the default config file
+  --server <url>      Connect to <url>, or to the profile of that name
+  --profile <name>    Use the named server profile from the config file
+  -h, --help          Print this message
+
+Environment: CLI_CHAT_CONFIG, CLI_CHAT_SERVER, CLI_CHAT_PROFILE,
+             CLI_CHAT_RPC_PATH, CLI_CHAT_LOGIN_PATH, CLI_CHAT_TIME_FORMAT,
+             CLI_CHAT_LOG";
+
+/// Where one server lives and which paths it serves the API on.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub base_url: String,
+    pub rpc_path: String,
+    pub login_path: String,
+    pub create_user_path: String,
+    pub ws_path: String,
+    /// Keeps what's saved for this server under here instead of the data
+    /// directory. Never read from the config file.
+    #[serde(skip)]
+    pub data_dir: Option<PathBuf>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            base_url: "http://localhost:8080".to_string(),
+            rpc_path: "/api/rpc".to_string(),
+            login_path: "/api/login".to_string(),
+            create_user_path: "/api/create_user".to_string(),
+            ws_path: "/api/ws".to_string(),
+            data_dir: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn rpc_url(&self) -> String {
+        self.url(&self.rpc_path)
+    }
+
+    pub fn login_url(&self) -> String {
+        self.url(&self.login_path)
+    }
+
+    pub fn create_user_url(&self) -> String {
+        self.url(&self.create_user_path)
+    }
+
+    /// The push endpoint, on the same host with the scheme swapped to ws/wss.
+    pub fn ws_url(&self) -> String {
+        let url = self.url(&self.ws_path);
+        match url.split_once("://") {
+            Some(("https", rest)) => format!("wss://{rest}"),
+            Some((_, rest)) => format!("ws://{rest}"),
+            None => url,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}/{}",
+            self.base_url.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
+    }
+}
+
+/// How messages are shown. Formats use strftime syntax and are applied in
+/// the local timezone.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct DisplayConfig {
+    /// Shown in front of each message.
+    pub time_format: String,
+    /// Used by the separator between days, beyond "Today" and "Yesterday".
+    pub date_format: String,
+    /// Colour fenced code blocks that name their language.
+    pub highlight_code: bool,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            time_format: "%H:%M".to_string(),
+            date_format: "%A, %-d %B %Y".to_string(),
+            highlight_code: true,
+        }
+    }
+}
+
+/// What makes a message stand out as meant for us.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct AlertsConfig {
+    /// Words that highlight a message like a mention of our name does.
+    pub keywords: Vec<String>,
+    /// Ring the terminal bell when a highlighted message arrives.
+    pub bell: bool,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        AlertsConfig {
+            keywords: Vec::new(),
+            bell: true,
+        }
+    }
+}
+
+/// Where the log goes and how much of it.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// The least severe level logged, or a filter such as `info,tui::rpc=trace`.
+    pub level: String,
+    /// Days of log files kept.
+    pub keep_files: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            level: "info".to_string(),
+            keep_files: 7,
+        }
+    }
+}
+
+/// The config file as written on disk.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ConfigFile {
+    default_server: Option<String>,
+    servers: BTreeMap<String, ServerConfig>,
+    display: DisplayConfig,
+    alerts: AlertsConfig,
+    logging: LoggingConfig,
+}
+
+pub struct Config {
+    /// Every known server profile, keyed by name.
+    pub servers: BTreeMap<String, ServerConfig>,
+    /// Name of the profile currently in use.
+    pub profile: String,
+    pub display: DisplayConfig,
+    pub alerts: AlertsConfig,
+    pub logging: LoggingConfig,
+    /// `display` and `alerts` before any account's own settings.
+    shared: (DisplayConfig, AlertsConfig),
+}
+
+impl Config {
+    /// Switches to the settings of the account kept in `dir`, or back to the
+    /// shared ones. An account's `settings.toml` can have `[display]` and
+    /// `[alerts]` sections; whatever it leaves out comes from the config file.
+    pub fn use_account(&mut self, dir: Option<&Path>) -> Result<(), Error> {
+        (self.display, self.alerts) = self.shared.clone();
+        let Some(path) = dir
+            .map(|dir| dir.join("settings.toml"))
+            .filter(|path| path.exists())
+        else {
+            return Ok(());
+        };
+
+        let fail = |err: String| Error::ConfigError(format!("{}: {err}", path.display()));
+        let content = fs::read_to_string(&path).map_err(|err| fail(err.to_string()))?;
+        let settings: toml::Table =
+            toml::from_str(&content).map_err(|err| fail(err.to_string()))?;
+        let display = overlay(&self.display, settings.get("display")).map_err(fail)?;
+        let alerts = overlay(&self.alerts, settings.get("alerts")).map_err(fail)?;
+        check_formats(&display)?;
+        self.display = display;
+        self.alerts = alerts;
+        Ok(())
+    }
+
+    pub fn server(&self) -> &ServerConfig {
+        &self.servers[&self.profile]
+    }
+
+    /// Switches to the profile after the current one, wrapping around.
+    pub fn next_profile(&mut self) {
+        let names: Vec<&String> = self.servers.keys().collect();
+        let current = names.iter().position(|name| **name == self.profile);
+        let next = current.map(|index| (index + 1) % names.len()).unwrap_or(0);
+        self.profile = names[next].clone();
+    }
+}
+
+/// `base` with the keys `overrides` sets replaced.
+fn overlay<T: Serialize + DeserializeOwned>(
+    base: &T,
+    overrides: Option<&toml::Value>,
+) -> Result<T, String> {
+    let mut merged = toml::Value::try_from(base).map_err(|err| err.to_string())?;
+    if let (toml::Value::Table(merged), Some(toml::Value::Table(overrides))) =
+        (&mut merged, overrides)
+    {
+        merged.extend(overrides.clone());
+    }
+    merged
+        .try_into()
+        .map_err(|err: toml::de::Error| err.to_string())
+}
+
+/// chrono panics when a format can't be applied, either because it doesn't
+/// parse or because it asks a date for the time of day, so try each one out
+/// here on the kind of value it's used with.
+fn check_formats(display: &DisplayConfig) -> Result<(), Error> {
+    let now = Local::now();
+    let trials = [
+        (
+            "time",
+            &display.time_format,
+            now.format(&display.time_format),
+        ),
+        (
+            "date",
+            &display.date_format,
+            now.date_naive().format(&display.date_format),
+        ),
+    ];
+    for (kind, format, formatted) in trials {
+        let mut out = String::new();
+        if write!(out, "{formatted}").is_err() {
+            return Err(Error::ConfigError(format!(
+                "'{format}' is not a valid {kind} format"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Command line flags, parsed by hand since there are only a few.
+#[derive(Default)]
+struct Args {
+    config: Option<PathBuf>,
+    server: Option<String>,
+    profile: Option<String>,
+}
+
+fn parse_args() -> Result<Args, Error> {
+    let mut args = Args::default();
+    let mut iter = env::args().skip(1);
+
+    while let Some(arg) = iter.next() {
+        let mut value = |flag: &str| {
+            iter.next()
+                .ok_or_else(|| Error::ArgsError(format!("{flag} needs a value\n\n{USAGE}")))
+        };
+        match arg.as_str() {
+            "--config" => args.config = Some(PathBuf::from(value("--config")?)),
+            "--server" => args.server = Some(value("--server")?),
+            "--profile" => args.profile = Some(value("--profile")?),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            other => {
+                return Err(Error::ArgsError(format!(
+                    "unknown argument '{other}'\n\n{USAGE}"
+                )))
+            }
+        }
+    }
+
+    Ok(args)
+}
+
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(APP_DIR)
+}
+
+/// Where logs go. Only Linux has a state directory, so elsewhere they sit
+/// with the data.
+pub fn state_dir() -> PathBuf {
+    dirs::state_dir()
+        .map(|dir| dir.join(APP_DIR))
+        .unwrap_or_else(data_dir)
+}
+
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(APP_DIR)
+}
+
+/// Where what's kept for one server goes, including each account's own directory.
+pub fn server_dir(server: &ServerConfig) -> PathBuf {
+    let data = server.data_dir.clone().unwrap_or_else(data_dir);
+    data.join(path_safe(&server.base_url))
+}
+
+/// Where what's kept for one account on one server goes.
+pub fn account_dir(server: &ServerConfig, username: &str) -> PathBuf {
+    server_dir(server).join(path_safe(username))
+}
+
+/// `name` with anything that could step outside a directory replaced.
+fn path_safe(name: &str) -> String {
+    name.chars()
+        .map(|ch| {
+            if ch.is_alphanumeric() || ch == '-' || ch == '_' {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Builds the config from, in increasing priority: built-in defaults, the
+/// config file, `CLI_CHAT_*` environment variables and command line flags.
+pub fn load() -> Result<Config, Error> {
+    let args = parse_args()?;
+
+    let explicit_path = args
+        .config
+        .or_else(|| env::var_os("CLI_CHAT_CONFIG").map(PathBuf::from));
+    let file = match &explicit_path {
+        Some(path) => read_config_file(path)?,
+        None => {
+            let path = config_dir().join("config.toml");
+            if path.exists() {
+                read_config_file(&path)?
+            } else {
+                ConfigFile::default()
+            }
+        }
+    };
+
+    let mut servers = file.servers;
+    servers
+        .entry(DEFAULT_PROFILE.to_string())
+        .or_insert_with(ServerConfig::default);
+
+    let mut profile = args
+        .profile
+        .or_else(|| env::var("CLI_CHAT_PROFILE").ok())
+        .or(file.default_server)
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+
+    // --server may name a profile or give a URL outright
+    let server_override = args.server.or_else(|| env::var("CLI_CHAT_SERVER").ok());
+    if let Some(server) = server_override {
+        if servers.contains_key(&server) {
+            profile = server;
+        } else if server.contains("://") {
+            let base = servers.get(&profile).cloned().unwrap_or_default();
+            profile = server.clone();
+            servers.insert(
+                server.clone(),
+                ServerConfig {
+                    base_url: server,
+                    ..base
+                },
+            );
+        } else {
+            return Err(Error::ArgsError(format!(
+                "'{server}' is neither a server URL nor a configured profile"
+            )));
+        }
+    }
+
+    let selected = servers.get_mut(&profile).ok_or_else(|| {
+        Error::ArgsError(format!("no server profile named '{profile}' in the config"))
+    })?;
+    if let Ok(path) = env::var("CLI_CHAT_RPC_PATH") {
+        selected.rpc_path = path;
+    }
+    if let Ok(path) = env::var("CLI_CHAT_LOGIN_PATH") {
+        selected.login_path = path;
+    }
+
+    let mut display = file.display;
+    if let Ok(format) = env::var("CLI_CHAT_TIME_FORMAT") {
+        display.time_format = format;
+    }
+    check_formats(&display)?;
+
+    let mut logging = file.logging;
+    if let Ok(level) = env::var("CLI_CHAT_LOG") {
+        logging.level = level;
+    }
+
+    Ok(Config {
+        servers,
+        profile,
+        shared: (display.clone(), file.alerts.clone()),
+        display,
+        alerts: file.alerts,
+        logging,
+    })
+}
+
+fn read_config_file(path: &Path) -> Result<ConfigFile, Error> {
+    let content = fs::read_to_string(path)
+        .map_err(|err| Error::ConfigError(format!("{}: {err}", path.display())))?;
+    toml::from_str(&content).map_err(|err| Error::ConfigError(format!("{}: {err}", path.display())))
+}
@@ -1,170 +1,588 @@
+use crate::logging::redacted;
+use crate::rpc::{RpcClient, RpcMethod};
+use crate::structs::Error;
+use chrono::{DateTime, Utc};
 use reqwest::cookie::Jar;
-use reqwest::{self, Client, Result};
+use reqwest::{self, Client, Response, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::{sync::Arc, time::Duration};
+use tracing::debug;
 
-const URL: &str = "http://localhost:8080/api/rpc";
-const LOGIN_URL: &str = "http://localhost:8080/api/login";
+const LONG_POLL_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long to wait for the server to accept a connection, and then for a
+/// whole request, before calling it unreachable. Long polls set their own.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// How many messages of a room's history are fetched at a time.
+pub const PAGE_SIZE: usize = 50;
 
-pub fn create_client_with_cookies() -> Client {
-    let jar = Jar::default();
-    Client::builder().cookie_store(true).build().unwrap()
+/// Builds the HTTP client around a cookie jar we keep a handle to, so the
+/// session cookie can also be sent on the WebSocket handshake.
+pub fn create_client_with_cookies() -> (Client, Arc<Jar>) {
+    let jar = Arc::new(Jar::default());
+    let client = Client::builder()
+        .cookie_provider(jar.clone())
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap();
+    (client, jar)
 }
 
-pub async fn login(client: &Client) -> Result<()> {
+pub async fn create_user(rpc: &RpcClient, username: &str, pwd: &str) -> Result<(), Error> {
     let req_create_user = json!({
-    "username": "Dallas",
-    "pwd": "hello"
+        "username": username,
+        "pwd": pwd
     });
+    debug!(request = %redacted(&req_create_user), "-> create_user");
 
-    let create = client
-        .post("http://localhost:8080/api/create_user")
+    let response = rpc
+        .http()
+        .post(rpc.server().create_user_url())
         .json(&req_create_user)
         .send()
         .await?;
 
-    if create.status().is_success() {
-    } else {
-        println!("User create failed with status code: {}", create.status());
-    }
+    check_auth_response(response).await
+}
 
+pub async fn login(rpc: &RpcClient, username: &str, pwd: &str) -> Result<(), Error> {
     let req_login = json!({
-    "username": "Dallas",
-    "pwd": "hello"
+        "username": username,
+        "pwd": pwd
     });
+    debug!(request = %redacted(&req_login), "-> login");
 
-    let response = client.post(LOGIN_URL).json(&req_login).send().await?;
+    let response = rpc
+        .http()
+        .post(rpc.server().login_url())
+        .json(&req_login)
+        .send()
+        .await?;
 
-    if response.status().is_success() {
-        Ok(())
-    } else {
-        return Err(response.error_for_status().unwrap_err()).into();
-    }
+    check_auth_response(response).await
 }
 
-#[derive(Deserialize)]
-pub struct RoomResponse {
-    pub id: i64,
-    pub result: Vec<Room>,
+/// Turns a failed login/create_user response into a readable error, using the
+/// server's `error.message` when it sends one.
+async fn check_auth_response(response: Response) -> Result<(), Error> {
+    let status = response.status();
+    debug!(%status, "<- {}", response.url().path());
+    if status.is_success() {
+        return Ok(());
+    }
+
+    let body: serde_json::Value = response.json().await.unwrap_or_default();
+    let message = body["error"]["message"]
+        .as_str()
+        .map(String::from)
+        .unwrap_or_else(|| match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                "Wrong username or password".to_string()
+            }
+            _ => format!("Server responded with {}", status),
+        });
+
+    Err(Error::AuthError(message))
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct Room {
     pub id: i64,
     pub title: String,
+    /// Whether the logged in user is a member; only members can post.
+    #[serde(default)]
+    pub joined: bool,
+    #[serde(default)]
+    pub topic: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Message {
+    pub id: i64,
+    pub message_text: String,
+    pub message_room_id: i64,
+    pub message_user_name: String,
+    /// The id the sender's client gave it, echoed back so it can tell its
+    /// own messages have arrived.
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// When the server stored it; older servers don't send this.
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub edited_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct DirectMessage {
+    pub id: i64,
+    pub message_text: String,
+    pub sender_user_name: String,
+    pub recipient_user_name: String,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub edited_at: Option<DateTime<Utc>>,
+}
+
+impl DirectMessage {
+    /// The other person in the conversation, from `username`'s point of view.
+    pub fn partner<'a>(&'a self, username: &str) -> &'a str {
+        if self.sender_user_name == username {
+            &self.recipient_user_name
+        } else {
+            &self.sender_user_name
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct FriendRequest {
+    pub id: i64,
+    pub from_user_name: String,
+    pub to_user_name: String,
 }
 
+/// Limits the server advertises; anything it leaves out isn't enforced.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct ServerInfo {
+    #[serde(default)]
+    pub max_message_length: Option<usize>,
+}
+
+/// Frames the server pushes over the WebSocket.
 #[derive(Deserialize)]
-struct MessageResponse {
-    id: i64,
-    result: Vec<Message>,
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum Push {
+    Message(Message),
+    DirectMessage(DirectMessage),
+    /// Our friends or friend requests changed; re-fetch them.
+    FriendsChanged,
+    /// A room was created, renamed or its members changed; re-fetch the list.
+    RoomsChanged,
 }
 
-#[derive(Deserialize, Serialize)]
-pub struct Message {
+#[derive(Serialize)]
+pub struct GetServerInfo;
+
+impl RpcMethod for GetServerInfo {
+    const METHOD: &'static str = "get_server_info";
+    type Output = ServerInfo;
+}
+
+#[derive(Serialize)]
+pub struct ListRooms;
+
+impl RpcMethod for ListRooms {
+    const METHOD: &'static str = "list_rooms";
+    type Output = Vec<Room>;
+}
+
+#[derive(Serialize)]
+pub struct CreateRoom {
+    pub title: String,
+}
+
+impl RpcMethod for CreateRoom {
+    const METHOD: &'static str = "create_room";
+    type Output = Room;
+}
+
+#[derive(Serialize)]
+pub struct RenameRoom {
     pub id: i64,
+    pub title: String,
+}
+
+impl RpcMethod for RenameRoom {
+    const METHOD: &'static str = "rename_room";
+    type Output = serde::de::IgnoredAny;
+}
+
+#[derive(Serialize)]
+pub struct SetRoomTopic {
+    pub id: i64,
+    pub topic: String,
+}
+
+impl RpcMethod for SetRoomTopic {
+    const METHOD: &'static str = "set_room_topic";
+    type Output = serde::de::IgnoredAny;
+}
+
+#[derive(Serialize)]
+pub struct JoinRoom {
+    pub id: i64,
+}
+
+impl RpcMethod for JoinRoom {
+    const METHOD: &'static str = "join_room";
+    type Output = serde::de::IgnoredAny;
+}
+
+#[derive(Serialize)]
+pub struct LeaveRoom {
+    pub id: i64,
+}
+
+impl RpcMethod for LeaveRoom {
+    const METHOD: &'static str = "leave_room";
+    type Output = serde::de::IgnoredAny;
+}
+
+/// Up to `limit` of a room's messages, the newest ones first in line: either
+/// the latest, or those older than `before_id` when scrolling back. `since_id`
+/// leaves out what's already been seen, without waiting for anything new.
+#[derive(Serialize)]
+pub struct GetMessagesPage {
+    pub room_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since_id: Option<i64>,
+    pub limit: usize,
+}
+
+impl RpcMethod for GetMessagesPage {
+    const METHOD: &'static str = "get_messages_by_room_id";
+    type Output = Vec<Message>;
+}
+
+/// Only the messages in a room newer than `since_id`.
+#[derive(Serialize)]
+pub struct GetMessagesSince {
+    pub room_id: i64,
+    pub since_id: i64,
+}
+
+impl RpcMethod for GetMessagesSince {
+    const METHOD: &'static str = "get_messages_by_room_id";
+    type Output = Vec<Message>;
+}
+
+#[derive(Serialize)]
+pub struct MessageForCreate {
     pub message_text: String,
     pub message_room_id: i64,
     pub message_user_name: String,
+    /// Lets the server recognise a retry of a message it already has.
+    pub client_id: String,
 }
 
-pub async fn get_rooms(client: &Client) -> Vec<Room> {
-    let req_list_rooms = json!({
-        "id": 1,
-        "method": "list_rooms"
-    });
+#[derive(Serialize)]
+pub struct SendMessage {
+    pub data: MessageForCreate,
+}
 
-    let response = client.post(URL).json(&req_list_rooms).send().await;
+impl RpcMethod for SendMessage {
+    const METHOD: &'static str = "send_message";
+    type Output = serde::de::IgnoredAny;
+}
 
-    match response {
-        Ok(resp) if resp.status().is_success() => {
-            let body_text = resp.text().await.unwrap_or_else(|err| {
-                eprintln!("Error reading response body: {:?}", err);
-                String::new() // or handle this error case accordingly
-            });
+/// Conversation with one friend, oldest first. `since_id` limits it to newer messages.
+#[derive(Serialize)]
+pub struct GetDirectMessages {
+    pub username: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since_id: Option<i64>,
+}
 
-            //println!("Received JSON: {}", body_text);
+impl RpcMethod for GetDirectMessages {
+    const METHOD: &'static str = "get_direct_messages";
+    type Output = Vec<DirectMessage>;
+}
 
-            let room_response: RoomResponse =
-                serde_json::from_str(&body_text).unwrap_or_else(|err| {
-                    eprintln!("Error parsing room data: {:?}", err);
-                    RoomResponse {
-                        id: 0,
-                        result: Vec::new(),
-                    } // Return an empty response or handle this error case accordingly
-                });
+#[derive(Serialize)]
+pub struct DirectMessageForCreate {
+    pub recipient_user_name: String,
+    pub message_text: String,
+    pub client_id: String,
+}
 
-            room_response.result
-        }
-        Ok(resp) => {
-            eprintln!("Room request failed with status code: {}", resp.status());
-            Vec::new() // or handle this error case accordingly
-        }
-        Err(err) => {
-            eprintln!("Error fetching rooms: {:?}", err);
-            Vec::new() // or handle this error case accordingly
-        }
-    }
+#[derive(Serialize)]
+pub struct SendDirectMessage {
+    pub data: DirectMessageForCreate,
 }
 
-pub async fn get_messages(client: &Client, room_id: i64) -> Vec<Message> {
-    let req_messages = json!({
-        "id": 1,
-        "method": "get_messages_by_room_id",
-        "params": room_id + 1,
-    });
+impl RpcMethod for SendDirectMessage {
+    const METHOD: &'static str = "send_direct_message";
+    type Output = serde::de::IgnoredAny;
+}
 
-    let response = client.post(URL).json(&req_messages).send().await;
+/// Changes the logged in user's name. The server re-issues the session
+/// cookie, since it names the user.
+#[derive(Serialize)]
+pub struct RenameUser {
+    pub username: String,
+}
 
-    match response {
-        Ok(resp) if resp.status().is_success() => {
-            let body_text = resp.text().await.unwrap_or_else(|err| {
-                eprintln!("Error reading response body: {:?}", err);
-                String::new() // or handle this error case accordingly
-            });
+impl RpcMethod for RenameUser {
+    const METHOD: &'static str = "rename_user";
+    type Output = serde::de::IgnoredAny;
+}
 
-            //println!("Received JSON: {}", body_text);
+#[derive(Serialize)]
+pub struct ListFriends;
 
-            let message_response: MessageResponse = serde_json::from_str(&body_text)
-                .unwrap_or_else(|err| {
-                    eprintln!("Error parsing room data: {:?}", err);
-                    MessageResponse {
-                        id: 0,
-                        result: Vec::new(),
-                    } // Return an empty response or handle this error case accordingly
-                });
+impl RpcMethod for ListFriends {
+    const METHOD: &'static str = "list_friends";
+    type Output = Vec<String>;
+}
 
-            message_response.result
-        }
-        Ok(resp) => {
-            eprintln!("Message request failed with status code: {}", resp.status());
-            Vec::new() // or handle this error case accordingly
-        }
-        Err(err) => {
-            eprintln!("Error fetching rooms: {:?}", err);
-            Vec::new() // or handle this error case accordingly
+/// Pending requests both to and from the logged in user.
+#[derive(Serialize)]
+pub struct ListFriendRequests;
+
+impl RpcMethod for ListFriendRequests {
+    const METHOD: &'static str = "list_friend_requests";
+    type Output = Vec<FriendRequest>;
+}
+
+#[derive(Serialize)]
+pub struct SendFriendRequest {
+    pub username: String,
+}
+
+impl RpcMethod for SendFriendRequest {
+    const METHOD: &'static str = "send_friend_request";
+    type Output = serde::de::IgnoredAny;
+}
+
+#[derive(Serialize)]
+pub struct AcceptFriendRequest {
+    pub id: i64,
+}
+
+impl RpcMethod for AcceptFriendRequest {
+    const METHOD: &'static str = "accept_friend_request";
+    type Output = serde::de::IgnoredAny;
+}
+
+#[derive(Serialize)]
+pub struct DeclineFriendRequest {
+    pub id: i64,
+}
+
+impl RpcMethod for DeclineFriendRequest {
+    const METHOD: &'static str = "decline_friend_request";
+    type Output = serde::de::IgnoredAny;
+}
+
+#[derive(Serialize)]
+pub struct RemoveFriend {
+    pub username: String,
+}
+
+impl RpcMethod for RemoveFriend {
+    const METHOD: &'static str = "remove_friend";
+    type Output = serde::de::IgnoredAny;
+}
+
+pub async fn get_server_info(rpc: &RpcClient) -> Result<ServerInfo, Error> {
+    rpc.call(&GetServerInfo).await
+}
+
+pub async fn get_rooms(rpc: &RpcClient) -> Result<Vec<Room>, Error> {
+    rpc.call(&ListRooms).await
+}
+
+/// Creates a room with the logged in user as its first member.
+pub async fn create_room(rpc: &RpcClient, title: &str) -> Result<Room, Error> {
+    let params = CreateRoom {
+        title: title.to_string(),
+    };
+    rpc.call(&params).await
+}
+
+pub async fn rename_room(rpc: &RpcClient, room_id: i64, title: &str) -> Result<(), Error> {
+    let params = RenameRoom {
+        id: room_id,
+        title: title.to_string(),
+    };
+    rpc.call(&params).await?;
+    Ok(())
+}
+
+pub async fn set_room_topic(rpc: &RpcClient, room_id: i64, topic: &str) -> Result<(), Error> {
+    let params = SetRoomTopic {
+        id: room_id,
+        topic: topic.to_string(),
+    };
+    rpc.call(&params).await?;
+    Ok(())
+}
+
+pub async fn join_room(rpc: &RpcClient, room_id: i64) -> Result<(), Error> {
+    rpc.call(&JoinRoom { id: room_id }).await?;
+    Ok(())
+}
+
+pub async fn leave_room(rpc: &RpcClient, room_id: i64) -> Result<(), Error> {
+    rpc.call(&LeaveRoom { id: room_id }).await?;
+    Ok(())
+}
+
+/// Fetches one page of a room's history, oldest first: the most recent
+/// messages, or the ones just before `before_id`. A page shorter than
+/// [`PAGE_SIZE`] means the start of the room has been reached.
+pub async fn get_messages(
+    rpc: &RpcClient,
+    room_id: i64,
+    before_id: Option<i64>,
+) -> Result<Vec<Message>, Error> {
+    let params = GetMessagesPage {
+        room_id,
+        before_id,
+        since_id: None,
+        limit: PAGE_SIZE,
+    };
+    rpc.call(&params).await
+}
+
+/// Everything in a room newer than `since_id`, a page at a time from the
+/// newest back. Unlike [`get_messages_since`] it answers straight away when
+/// there's nothing new.
+pub async fn get_messages_after(
+    rpc: &RpcClient,
+    room_id: i64,
+    since_id: i64,
+) -> Result<Vec<Message>, Error> {
+    let mut messages = Vec::new();
+    let mut before_id = None;
+    loop {
+        let params = GetMessagesPage {
+            room_id,
+            before_id,
+            since_id: Some(since_id),
+            limit: PAGE_SIZE,
+        };
+        let mut page: Vec<Message> = rpc.call(&params).await?;
+        // A server that ignores since_id would otherwise be paged back to the start
+        let more = page.len() == PAGE_SIZE && page.iter().all(|message| message.id > since_id);
+        page.retain(|message| message.id > since_id);
+        before_id = page.first().map(|message| message.id);
+        page.append(&mut messages);
+        messages = page;
+        if !more {
+            return Ok(messages);
         }
     }
 }
 
-pub async fn send_message(client: &Client, room_id: i64, text: Vec<String>) -> Result<()> {
-    let message = text.join(" ");
-    let send_message = json!({
-                "method": "send_message",
-                "params": {
-                "data": {
-                   "message_text": message,
-                   "message_room_id": room_id + 1,
-                   "message_user_name": "Dallas",
-                }
-            }
-    });
+/// Fetches only the messages in a room newer than `since_id`. The server may
+/// hold the request open until something arrives, so this allows much longer
+/// than a normal call.
+pub async fn get_messages_since(
+    rpc: &RpcClient,
+    room_id: i64,
+    since_id: i64,
+) -> Result<Vec<Message>, Error> {
+    let params = GetMessagesSince { room_id, since_id };
+    rpc.call_with_timeout(&params, LONG_POLL_TIMEOUT).await
+}
 
-    let response = client.post(URL).json(&send_message).send().await?;
+pub async fn send_message(
+    rpc: &RpcClient,
+    username: &str,
+    room_id: i64,
+    client_id: &str,
+    text: &str,
+) -> Result<(), Error> {
+    let params = SendMessage {
+        data: MessageForCreate {
+            message_text: text.to_string(),
+            message_room_id: room_id,
+            message_user_name: username.to_string(),
+            client_id: client_id.to_string(),
+        },
+    };
+    rpc.call(&params).await?;
+    Ok(())
+}
 
-    if response.status().is_success() {
-        Ok(())
-    } else {
-        return Err(response.error_for_status().unwrap_err()).into();
-    }
+pub async fn get_direct_messages(
+    rpc: &RpcClient,
+    friend: &str,
+) -> Result<Vec<DirectMessage>, Error> {
+    let params = GetDirectMessages {
+        username: friend.to_string(),
+        since_id: None,
+    };
+    rpc.call(&params).await
+}
+
+/// Like [`get_messages_since`], for a private conversation.
+pub async fn get_direct_messages_since(
+    rpc: &RpcClient,
+    friend: &str,
+    since_id: i64,
+) -> Result<Vec<DirectMessage>, Error> {
+    let params = GetDirectMessages {
+        username: friend.to_string(),
+        since_id: Some(since_id),
+    };
+    rpc.call_with_timeout(&params, LONG_POLL_TIMEOUT).await
+}
+
+pub async fn send_direct_message(
+    rpc: &RpcClient,
+    friend: &str,
+    client_id: &str,
+    text: &str,
+) -> Result<(), Error> {
+    let params = SendDirectMessage {
+        data: DirectMessageForCreate {
+            recipient_user_name: friend.to_string(),
+            message_text: text.to_string(),
+            client_id: client_id.to_string(),
+        },
+    };
+    rpc.call(&params).await?;
+    Ok(())
+}
+
+pub async fn rename_user(rpc: &RpcClient, username: &str) -> Result<(), Error> {
+    let params = RenameUser {
+        username: username.to_string(),
+    };
+    rpc.call(&params).await?;
+    Ok(())
+}
+
+pub async fn get_friends(rpc: &RpcClient) -> Result<Vec<String>, Error> {
+    rpc.call(&ListFriends).await
+}
+
+pub async fn get_friend_requests(rpc: &RpcClient) -> Result<Vec<FriendRequest>, Error> {
+    rpc.call(&ListFriendRequests).await
+}
+
+pub async fn send_friend_request(rpc: &RpcClient, username: &str) -> Result<(), Error> {
+    let params = SendFriendRequest {
+        username: username.to_string(),
+    };
+    rpc.call(&params).await?;
+    Ok(())
+}
+
+pub async fn accept_friend_request(rpc: &RpcClient, id: i64) -> Result<(), Error> {
+    rpc.call(&AcceptFriendRequest { id }).await?;
+    Ok(())
+}
+
+pub async fn decline_friend_request(rpc: &RpcClient, id: i64) -> Result<(), Error> {
+    rpc.call(&DeclineFriendRequest { id }).await?;
+    Ok(())
+}
+
+pub async fn remove_friend(rpc: &RpcClient, username: &str) -> Result<(), Error> {
+    let params = RemoveFriend {
+        username: username.to_string(),
+    };
+    rpc.call(&params).await?;
+    Ok(())
 }
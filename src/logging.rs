@@ -0,0 +1,151 @@
+use crate::config::{state_dir, LoggingConfig};
+use crate::structs::Error;
+use chrono::{DateTime, Local};
+use serde_json::Value;
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write},
+    fs,
+    sync::{Arc, Mutex},
+};
+use tracing::{field::Field, Event, Level, Subscriber};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{self, Rotation},
+};
+use tracing_subscriber::{field::Visit, layer::Context, prelude::*, EnvFilter, Layer};
+
+/// Lines the debug panel keeps; older ones are still in the file.
+const PANEL_LINES: usize = 500;
+/// Keys whose values never reach the log.
+const SECRET_KEYS: [&str; 5] = ["pwd", "password", "token", "cookie", "secret"];
+
+/// One event as the debug panel shows it.
+pub struct LogLine {
+    pub time: DateTime<Local>,
+    pub level: Level,
+    pub target: String,
+    pub text: String,
+}
+
+/// The latest log lines, shared between the logger and the debug panel.
+#[derive(Clone, Default)]
+pub struct LogBuffer(Arc<Mutex<VecDeque<LogLine>>>);
+
+impl LogBuffer {
+    /// Runs `show` over the lines kept, oldest first.
+    pub fn with_lines<R>(&self, show: impl FnOnce(&VecDeque<LogLine>) -> R) -> R {
+        show(&self.0.lock().unwrap_or_else(|err| err.into_inner()))
+    }
+
+    fn push(&self, line: LogLine) {
+        let mut lines = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        if lines.len() == PANEL_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogBuffer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        self.push(LogLine {
+            time: Local::now(),
+            level: *event.metadata().level(),
+            target: event.metadata().target().to_string(),
+            text: fields.message + &fields.rest,
+        });
+    }
+}
+
+/// An event's message, and its other fields as ` name=value`.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    rest: String,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message.push_str(value),
+            name => {
+                let _ = write!(self.rest, " {name}={value}");
+            }
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => {
+                let _ = write!(self.message, "{value:?}");
+            }
+            name => {
+                let _ = write!(self.rest, " {name}={value:?}");
+            }
+        }
+    }
+}
+
+/// Sends log events to a file under the state directory, started afresh each
+/// day with only the newest few kept, and to the debug panel. The file
+/// writer flushes until the returned guard is dropped.
+pub fn init(config: &LoggingConfig) -> Result<(LogBuffer, WorkerGuard), Error> {
+    // A bare level only applies to our own events: the HTTP libraries log
+    // cookies at debug level, and drown everything else out besides
+    let filter = match config.level.parse::<Level>() {
+        Ok(level) => format!("warn,{}={level}", env!("CARGO_CRATE_NAME")),
+        Err(_) => config.level.clone(),
+    };
+    let filter = EnvFilter::try_new(filter).map_err(|err| Error::ConfigError(err.to_string()))?;
+    let dir = state_dir().join("logs");
+    // The appender complains on stderr if it can't list old logs to prune
+    fs::create_dir_all(&dir)
+        .map_err(|err| Error::ConfigError(format!("{}: {err}", dir.display())))?;
+    let appender = rolling::Builder::new()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("tui")
+        .filename_suffix("log")
+        .max_log_files(config.keep_files.max(1))
+        .build(&dir)
+        .map_err(|err| Error::ConfigError(format!("{}: {err}", dir.display())))?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+
+    let buffer = LogBuffer::default();
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false),
+        )
+        .with(buffer.clone())
+        .init();
+    Ok((buffer, guard))
+}
+
+/// `value` as compact JSON with anything that looks like a secret blanked out.
+pub fn redacted(value: &Value) -> String {
+    let mut value = value.clone();
+    redact(&mut value);
+    value.to_string()
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let key = key.to_lowercase();
+                if SECRET_KEYS.iter().any(|secret| key.contains(secret)) {
+                    *value = Value::String("[redacted]".to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
@@ -0,0 +1,163 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    widgets::{Block, Borders},
+};
+use tui_textarea::{Input, Key, TextArea};
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum LoginField {
+    Username,
+    Password,
+}
+
+/// What the login form wants the caller to do after handling a key.
+pub enum LoginAction {
+    None,
+    Submit {
+        username: String,
+        pwd: String,
+        register: bool,
+    },
+}
+
+pub struct LoginForm<'a> {
+    pub username: TextArea<'a>,
+    pub password: TextArea<'a>,
+    pub focus: LoginField,
+    pub register: bool,
+    pub error: Option<String>,
+    /// A login request is in flight; further submits are ignored until it returns.
+    pub pending: bool,
+}
+
+impl<'a> LoginForm<'a> {
+    pub fn new() -> Self {
+        let mut username = TextArea::default();
+        username.set_placeholder_text("Username");
+
+        let mut password = TextArea::default();
+        password.set_placeholder_text("Password");
+        password.set_mask_char('\u{2022}'); //U+2022 BULLET (•)
+
+        let mut form = LoginForm {
+            username,
+            password,
+            focus: LoginField::Username,
+            register: false,
+            error: None,
+            pending: false,
+        };
+        form.update_styles();
+        form
+    }
+
+    pub fn handle_input(&mut self, input: Input) -> LoginAction {
+        match input {
+            Input {
+                key: Key::Char('n'),
+                ctrl: true,
+                ..
+            } => {
+                self.register = !self.register;
+                self.error = None;
+            }
+            Input { key: Key::Tab, .. }
+            | Input { key: Key::Down, .. }
+            | Input { key: Key::Up, .. } => self.toggle_focus(),
+            Input {
+                key: Key::Enter, ..
+            } => match self.focus {
+                LoginField::Username => self.toggle_focus(),
+                LoginField::Password if self.pending => {}
+                LoginField::Password => match self.validate() {
+                    Ok((username, pwd)) => {
+                        self.error = None;
+                        return LoginAction::Submit {
+                            username,
+                            pwd,
+                            register: self.register,
+                        };
+                    }
+                    Err(err) => self.error = Some(err),
+                },
+            },
+            input => {
+                match self.focus {
+                    LoginField::Username => self.username.input(input),
+                    LoginField::Password => self.password.input(input),
+                };
+            }
+        }
+        self.update_styles();
+        LoginAction::None
+    }
+
+    /// Fills in the username, such as when logging back in as the same user.
+    pub fn set_username(&mut self, username: &str) {
+        self.username.select_all();
+        self.username.cut();
+        self.username.insert_str(username);
+    }
+
+    /// Clears the password after a failed attempt and puts the cursor back in it.
+    pub fn reset_password(&mut self) {
+        self.password.select_all();
+        self.password.cut();
+        self.focus = LoginField::Password;
+        self.update_styles();
+    }
+
+    fn toggle_focus(&mut self) {
+        self.focus = match self.focus {
+            LoginField::Username => LoginField::Password,
+            LoginField::Password => LoginField::Username,
+        };
+    }
+
+    fn validate(&self) -> Result<(String, String), String> {
+        let username = self.username.lines()[0].trim().to_string();
+        let pwd = self.password.lines()[0].clone();
+
+        if username.is_empty() {
+            return Err("Username is required".to_string());
+        }
+        if username.chars().any(char::is_whitespace) {
+            return Err("Username cannot contain spaces".to_string());
+        }
+        if pwd.is_empty() {
+            return Err("Password is required".to_string());
+        }
+        if self.register && pwd.chars().count() < 4 {
+            return Err("Password must be at least 4 characters".to_string());
+        }
+
+        Ok((username, pwd))
+    }
+
+    fn update_styles(&mut self) {
+        let focus = self.focus;
+        for (field, textarea, title) in [
+            (LoginField::Username, &mut self.username, "Username"),
+            (LoginField::Password, &mut self.password, "Password"),
+        ] {
+            let color = if field == focus {
+                Color::Yellow
+            } else {
+                Color::White
+            };
+            textarea.set_block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(color))
+                    .title(title),
+            );
+            textarea.set_style(Style::default().fg(Color::Yellow));
+            textarea.set_cursor_line_style(Style::default());
+            textarea.set_cursor_style(if field == focus {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            });
+        }
+    }
+}
@@ -1,18 +1,46 @@
+mod account;
+mod alerts;
+mod cache;
+mod commands;
+mod completion;
+mod config;
 mod interact;
+mod logging;
+mod login;
+mod network;
+mod outbox;
+mod read_state;
 mod render;
+mod rpc;
+mod screen;
+mod session;
 pub mod structs;
+mod subscription;
 
-use crate::render::chat_room::render_chat_rooms;
-use crate::render::friends::{read_db, render_friends};
-use crate::render::home::render_home;
+use crate::render::chat_room::{render_chat_rooms, RoomsPane};
+use crate::render::composer::{
+    completion_area, composer_block, composer_height, message_length, render_completions,
+};
+use crate::render::debug_log::render_debug_log;
+use crate::render::friends::{render_friends, FriendOptions};
+use crate::render::home::{render_home, render_login_status, AccountList};
+use crate::render::mentions::render_mentions;
+use crate::render::status::{render_status, StatusBar};
+use crate::render::timeline::TimelineStyle;
 
-use interact::{create_client_with_cookies, login, send_message, Message};
+use interact::{Message, PAGE_SIZE};
+use login::{LoginAction, LoginField, LoginForm};
+use network::{Command, Credentials};
+use outbox::{Outbox, Outgoing};
+use read_state::ReadState;
+use session::SavedSession;
 
-use crossterm::{
-    event::{self, DisableMouseCapture, Event as CEvent, KeyCode},
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, LeaveAlternateScreen},
-};
+use account::Account;
+use cache::MessageCache;
+
+use commands::SlashCommand;
+use completion::{Completion, Sources};
+use crossterm::event::{self, Event as CEvent, KeyCode, KeyModifiers};
 use ratatui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout},
@@ -22,10 +50,10 @@ use ratatui::{
     widgets::*,
     Terminal,
 };
-use structs::{Event, MenuItem, Mode};
+use structs::{Connection, Conversation, Event, MenuItem, Mode, Prompt, Scrollback, Status};
 
 use std::{
-    io,
+    io::{self, Write},
     sync::mpsc,
     thread,
     time::{Duration, Instant},
@@ -33,12 +61,171 @@ use std::{
 
 use tui_textarea::{Input, Key, TextArea};
 
+/// The item `step` places away from the selected one, staying put at either end.
+fn step_selection<T: Clone>(
+    items: &[T],
+    is_selected: impl Fn(&T) -> bool,
+    step: isize,
+) -> Option<T> {
+    let index = items
+        .iter()
+        .position(is_selected)
+        .map(|index| index.saturating_add_signed(step).min(items.len() - 1))
+        .unwrap_or(0);
+    items.get(index).cloned()
+}
+
+/// Adds `message` to the mentions if someone else's message there mentions
+/// us or a keyword. Returns whether it was new.
+fn note_mention(
+    mentions: &mut Vec<Message>,
+    message: &Message,
+    username: &str,
+    keywords: &[String],
+) -> bool {
+    let new = message.message_user_name != username
+        && !mentions.iter().any(|known| known.id == message.id)
+        && alerts::is_highlighted(&message.message_text, username, keywords);
+    if new {
+        mentions.push(message.clone());
+        mentions.sort_by_key(|mention| (mention.created_at, mention.id));
+    }
+    new
+}
+
+/// Scrolls to the message being jumped to if it's among those loaded.
+/// Otherwise returns the request for the page before them, or an error once
+/// the room's history runs out.
+fn seek_message(
+    target: i64,
+    room_id: i64,
+    chat: &[Message],
+    scroll: &mut Scrollback,
+) -> Result<Option<Command>, &'static str> {
+    if let Some(index) = chat.iter().position(|message| message.id == target) {
+        scroll.show(index, chat.len());
+        return Ok(None);
+    }
+    match chat.first() {
+        Some(oldest) if !scroll.reached_start => {
+            scroll.loading = true;
+            Ok(Some(Command::GetOlderMessages {
+                room_id,
+                before_id: oldest.id,
+            }))
+        }
+        _ => Err("That message is no longer in the room's history"),
+    }
+}
+
+/// Shows what's cached for a room straight away, then asks the server for
+/// anything newer, or for the latest page when nothing is cached.
+fn open_room(
+    room_id: i64,
+    cache: &mut MessageCache,
+    chat: &mut Vec<Message>,
+    net: &tokio::sync::mpsc::UnboundedSender<Command>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    *chat = cache.messages(room_id);
+    net.send(Command::GetMessages {
+        room_id,
+        since_id: chat.last().map(|message| message.id),
+    })?;
+    Ok(())
+}
+
+/// Asks again for everything on screen, for when the server may have moved
+/// on without us. What's shown stays until the answers come in.
+fn catch_up(
+    account: &mut Account,
+    net: &tokio::sync::mpsc::UnboundedSender<Command>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(room) = &account.selected_room {
+        open_room(room.id, &mut account.cache, &mut account.chat, net)?;
+    }
+    net.send(Command::GetRooms)?;
+    net.send(Command::GetFriends)?;
+    if let Some(friend) = &account.selected_friend {
+        net.send(Command::GetDirectMessages {
+            friend: friend.clone(),
+        })?;
+    }
+    Ok(())
+}
+
+/// Hands a queued message to the network task.
+fn send_outgoing(
+    message: Outgoing,
+    net: &tokio::sync::mpsc::UnboundedSender<Command>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    net.send(match message.conversation {
+        Conversation::Room(room_id) => Command::SendMessage {
+            room_id,
+            client_id: message.client_id,
+            text: message.text,
+        },
+        Conversation::Direct(friend) => Command::SendDirectMessage {
+            friend,
+            client_id: message.client_id,
+            text: message.text,
+        },
+    })?;
+    Ok(())
+}
+
+/// Logs in as a saved account without a password if its session is still
+/// saved, otherwise fills in its username for the login form.
+fn pick_account(
+    saved: &SavedSession,
+    server: &config::ServerConfig,
+    login_form: &mut LoginForm,
+    net: &tokio::sync::mpsc::UnboundedSender<Command>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    login_form.set_username(&saved.username);
+    login_form.register = false;
+    login_form.error = None;
+    login_form.reset_password();
+    if saved.resumable() {
+        login_form.pending = true;
+        net.send(Command::Login {
+            server: server.clone(),
+            username: saved.username.clone(),
+            credentials: Credentials::Saved(saved.clone()),
+        })?;
+    }
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    enable_raw_mode()?;
+    let mut config = match config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(2);
+        }
+    };
+    // Flushes the log file when dropped, so it has to outlive the UI
+    let (log, _log_guard) = match logging::init(&config.logging) {
+        Ok(logging) => logging,
+        Err(err) => {
+            eprintln!("Logging: {err}");
+            std::process::exit(2);
+        }
+    };
+    tracing::info!(server = %config.server().base_url, "starting");
+
+    screen::install_panic_hook();
+    // Puts the terminal back however this returns
+    let _screen = screen::Guard::enter()?;
 
     let (tx, rx) = mpsc::channel();
     let tick_rate = Duration::from_millis(200);
 
+    // One runtime for the whole session; all HTTP goes through the network task on it
+    let runtime = tokio::runtime::Runtime::new()?;
+    let net = network::spawn(&runtime, tx.clone());
+    runtime.spawn(screen::quit_signals(tx.clone()));
+
     thread::spawn(move || {
         let mut last_tick = Instant::now();
         loop {
@@ -46,49 +233,108 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .checked_sub(last_tick.elapsed())
                 .unwrap_or_else(|| Duration::from_secs(0));
 
-            if event::poll(timeout).expect("poll works") {
-                if let CEvent::Key(key) = event::read().expect("can read events") {
-                    tx.send(Event::Input(key)).expect("can send events");
+            // Without input there's no way to use the app, or to quit it
+            let key = match event::poll(timeout) {
+                Ok(true) => event::read().map(|event| match event {
+                    CEvent::Key(key) => Some(key),
+                    _ => None,
+                }),
+                Ok(false) => Ok(None),
+                Err(err) => Err(err),
+            };
+            let sent = match key {
+                Ok(Some(key)) => tx.send(Event::Input(key)),
+                Ok(None) => Ok(()),
+                Err(err) => {
+                    tracing::error!("can't read the terminal: {err}");
+                    let _ = tx.send(Event::Quit);
+                    return;
                 }
+            };
+            // The UI has gone
+            if sent.is_err() {
+                return;
             }
-            if last_tick.elapsed() >= tick_rate {
-                if let Ok(_) = tx.send(Event::Tick) {
-                    last_tick = Instant::now();
-                }
+            if last_tick.elapsed() >= tick_rate && tx.send(Event::Tick).is_ok() {
+                last_tick = Instant::now();
             }
         }
     });
 
-    let client = create_client_with_cookies();
-
-    tokio::runtime::Runtime::new()
-        .unwrap()
-        .block_on(async { login(&client).await });
-
-    let chat_rooms = tokio::runtime::Runtime::new()
-        .unwrap()
-        .block_on(async { interact::get_rooms(&client).await });
-
-    let mut stdout = io::stdout();
+    let stdout = io::stdout();
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
     terminal.clear()?;
 
     let mut textarea = TextArea::default();
+    let mut login_form = LoginForm::new();
+
+    // Set once the login form succeeds; nothing past the Home tab is reachable until then.
+    let mut username: Option<String> = None;
+    // Whatever belongs to whoever that is
+    let mut account = Account::default();
 
-    let menu_titles = vec!["Home", "Chat Rooms", "Friends List"];
+    let menu_titles = ["Home", "Chat Rooms", "Friends List", "Mentions"];
 
     let mut mode = Mode::Normal;
     let mut active_menu_item = MenuItem::Home;
-    let mut chat_list_state = ListState::default();
-    let mut friends_list_state = ListState::default();
-    let mut room_list_state = ListState::default();
+    // Messages that fit in the chat pane, as of the last draw
+    let mut chat_page: usize = 1;
+
+    // Outcome of the last command, shown under everything else
+    let mut status: Option<Status> = None;
+    let mut connection = Connection::default();
+    // F12 shows the latest log lines over the lower half of the page
+    let mut show_log = false;
+
+    // Who was logged in when the session expired and which tab they were on,
+    // so logging back in carries on where they left off
+    let mut signed_out: Option<(String, MenuItem)> = None;
+
+    // Accounts that have logged in to this server before, for the picker on Home
+    let mut accounts = session::accounts(config.server());
+    let mut selected_account: Option<String> = None;
+    // Picked on Home while another account was logged in; logged in to once
+    // that one is logged out
+    let mut switch_to: Option<SavedSession> = None;
 
-    chat_list_state.select(Some(0));
-    friends_list_state.select(Some(0));
-    room_list_state.select(Some(0));
+    // Carry on as whoever was logged in last time, if the server still knows them
+    if let Some(saved) = accounts.first() {
+        pick_account(saved, config.server(), &mut login_form, &net)?;
+    }
 
     loop {
+        // Whatever is on screen counts as read
+        let visible = match active_menu_item {
+            MenuItem::ChatRooms => account
+                .selected_room
+                .as_ref()
+                .zip(account.chat.last())
+                .map(|(room, newest)| (Conversation::Room(room.id), newest.id)),
+            MenuItem::FriendsList => account
+                .selected_friend
+                .clone()
+                .zip(account.dm_chat.last())
+                .map(|(friend, newest)| (Conversation::Direct(friend), newest.id)),
+            _ => None,
+        };
+        if let Some((conversation, newest)) = visible {
+            account.read_state.mark_read(&conversation, newest);
+        }
+        if let Err(err) = account.read_state.save() {
+            status = Some(Status::error(format!(
+                "Couldn't save what's been read: {err}"
+            )));
+        }
+        if let Err(err) = account.outbox.save() {
+            status = Some(Status::error(format!(
+                "Couldn't save unsent messages: {err}"
+            )));
+        }
+        if let Some(err) = account.cache.take_error() {
+            status = Some(Status::error(format!("Message cache: {err}")));
+        }
+
         terminal.draw(|rect| {
             let size = rect.size();
             let chunks = Layout::default()
@@ -128,34 +374,64 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .divider(Span::raw("|"));
 
             rect.render_widget(tabs, chunks[0]);
+            let conversation = match active_menu_item {
+                MenuItem::ChatRooms => account
+                    .selected_room
+                    .as_ref()
+                    .map(|room| format!("#{}", room.title)),
+                MenuItem::FriendsList => account
+                    .selected_friend
+                    .as_ref()
+                    .map(|friend| format!("@{friend}")),
+                _ => None,
+            };
+            let bar = StatusBar {
+                connection: &connection,
+                username: username.as_deref(),
+                conversation,
+                mode,
+            };
+            rect.render_widget(render_status(status.as_ref(), &bar), chunks[2]);
             match active_menu_item {
                 MenuItem::Home => {
-                    let login_block = Layout::default()
+                    let home_chunks = Layout::default()
                         .direction(Direction::Horizontal)
                         .constraints(
                             [Constraint::Percentage(50), Constraint::Percentage(50)].as_ref(),
                         )
                         .split(chunks[1]);
 
-                    textarea.set_block(
-                        Block::default()
-                            .borders(Borders::ALL)
-                            .border_style(Style::default().fg(Color::Red))
-                            .title("Enter Username and Password"),
+                    rect.render_widget(render_home(), home_chunks[0]);
+                    rect.render_widget(
+                        render_login_status(
+                            &login_form,
+                            &config,
+                            username.as_deref(),
+                            &AccountList {
+                                accounts: &accounts,
+                                selected: selected_account.as_deref(),
+                            },
+                        ),
+                        home_chunks[1],
                     );
 
-                    let area = Rect {
-                        width: 40,
-                        height: 5,
-                        x: 75,
-                        y: 20,
-                    };
-                    textarea.set_style(Style::default().fg(Color::Yellow));
-                    textarea.set_cursor_line_style(Style::default());
-                    //textarea.set_mask_char('\u{2022}'); //U+2022 BULLET (•)
-                    textarea.set_placeholder_text("Please enter your password");
-                    rect.render_widget(render_home(), chunks[1]);
-                    rect.render_widget(textarea.widget(), area);
+                    if username.is_none() {
+                        let form_chunks = Layout::default()
+                            .direction(Direction::Vertical)
+                            .margin(1)
+                            .constraints(
+                                [
+                                    Constraint::Length(3),
+                                    Constraint::Length(3),
+                                    Constraint::Min(0),
+                                ]
+                                .as_ref(),
+                            )
+                            .split(home_chunks[1]);
+
+                        rect.render_widget(login_form.username.widget(), form_chunks[0]);
+                        rect.render_widget(login_form.password.widget(), form_chunks[1]);
+                    }
                 }
 
                 MenuItem::ChatRooms => {
@@ -166,29 +442,69 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         )
                         .split(chunks[1]);
 
-                    let (left, right) = render_chat_rooms(&chat_rooms, &client, &room_list_state);
-                    rect.render_widget(left, chat_chunks[1]);
-                    rect.render_widget(right, chat_chunks[0]);
-
                     let textwindow = Layout::default()
                         .direction(Direction::Vertical)
                         .constraints(
-                            [Constraint::Percentage(90), Constraint::Percentage(10)].as_ref(),
+                            [
+                                Constraint::Min(1),
+                                Constraint::Length(composer_height(&textarea)),
+                            ]
+                            .as_ref(),
                         )
                         .split(chat_chunks[1]);
 
-                    textarea.set_block(
-                        Block::default()
-                            .borders(Borders::ALL)
-                            .border_style(Style::default().fg(Color::Green))
-                            .title("Input"),
+                    let selected_id = account.selected_room.as_ref().map(|room| room.id);
+                    let scroll = selected_id
+                        .and_then(|id| account.scrollbacks.get(&id).copied())
+                        .unwrap_or_default();
+                    let conversation = selected_id.map(Conversation::Room);
+                    let unsent: Vec<&Outgoing> = conversation
+                        .iter()
+                        .flat_map(|conversation| account.outbox.in_conversation(conversation))
+                        .collect();
+                    let (left, mut left_state, right) = render_chat_rooms(
+                        &RoomsPane {
+                            rooms: &account.chat_rooms,
+                            selected: selected_id,
+                            error: account.rooms_error.as_deref(),
+                            read: &account.read_state,
+                        },
+                        &account.chat,
+                        &unsent,
+                        account.chat_error.as_deref(),
+                        &scroll,
+                        &TimelineStyle {
+                            display: &config.display,
+                            username: username.as_deref().unwrap_or_default(),
+                            keywords: &config.alerts.keywords,
+                            width: textwindow[0].width.saturating_sub(2),
+                            unread_after: account
+                                .room_divider
+                                .filter(|(room_id, _)| Some(*room_id) == selected_id)
+                                .and_then(|(_, read)| read),
+                        },
                     );
 
+                    // Kept clear of the input box so the newest messages aren't hidden under it
+                    rect.render_stateful_widget(left, textwindow[0], &mut left_state);
+                    rect.render_widget(right, chat_chunks[0]);
+                    chat_page = usize::from(textwindow[0].height.saturating_sub(2)).max(1);
+
+                    let block =
+                        composer_block(account.prompt, &textarea, account.max_message_length);
+                    textarea.set_block(block);
+
                     textarea.set_style(Style::default().fg(Color::Yellow));
                     textarea.set_placeholder_style(Style::default());
                     textarea.set_placeholder_text("Enter text here.");
 
                     rect.render_widget(textarea.widget(), textwindow[1]);
+                    if let Some(completion) = &account.completion {
+                        let area = completion_area(textwindow[1], completion);
+                        let (list, mut state) = render_completions(completion);
+                        rect.render_widget(Clear, area);
+                        rect.render_stateful_widget(list, area, &mut state);
+                    }
                 }
 
                 MenuItem::FriendsList => {
@@ -199,9 +515,6 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         )
                         .split(chunks[1]);
 
-                    let (left, center, right) = render_friends(&friends_list_state);
-                    rect.render_widget(left, friends_chunks[0]);
-
                     let friend_window = Layout::default()
                         .direction(Direction::Horizontal)
                         .constraints(
@@ -209,84 +522,1260 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         )
                         .split(friends_chunks[1]);
 
-                    rect.render_widget(center, friend_window[0]);
+                    let conversation = account.selected_friend.clone().map(Conversation::Direct);
+                    let unsent: Vec<&Outgoing> = conversation
+                        .iter()
+                        .flat_map(|conversation| account.outbox.in_conversation(conversation))
+                        .collect();
+                    let (left, center, mut center_state, right) = render_friends(
+                        &account.friends,
+                        account.selected_friend.as_deref(),
+                        &account.dm_chat,
+                        account.dm_error.as_deref(),
+                        &FriendOptions {
+                            username: username.as_deref().unwrap_or_default(),
+                            requests: &account.friend_requests,
+                            selected_request: account.selected_request,
+                            confirm_remove: account.confirm_remove,
+                            error: account.friends_error.as_deref(),
+                            read: &account.read_state,
+                            unsent: &unsent,
+                            timeline: TimelineStyle {
+                                display: &config.display,
+                                username: username.as_deref().unwrap_or_default(),
+                                keywords: &config.alerts.keywords,
+                                width: friend_window[0].width.saturating_sub(2),
+                                unread_after: account
+                                    .dm_divider
+                                    .as_ref()
+                                    .filter(|(friend, _)| {
+                                        Some(friend) == account.selected_friend.as_ref()
+                                    })
+                                    .and_then(|(_, read)| *read),
+                            },
+                        },
+                    );
+                    rect.render_widget(left, friends_chunks[0]);
+                    let textwindow = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints(
+                            [
+                                Constraint::Min(1),
+                                Constraint::Length(composer_height(&textarea)),
+                            ]
+                            .as_ref(),
+                        )
+                        .split(friend_window[0]);
+
+                    rect.render_stateful_widget(center, textwindow[0], &mut center_state);
                     rect.render_widget(right, friend_window[1]);
+
+                    let block =
+                        composer_block(account.prompt, &textarea, account.max_message_length);
+                    textarea.set_block(block);
+                    textarea.set_style(Style::default().fg(Color::Yellow));
+                    textarea.set_placeholder_style(Style::default());
+                    textarea.set_placeholder_text("Enter text here.");
+
+                    rect.render_widget(textarea.widget(), textwindow[1]);
+                    if let Some(completion) = &account.completion {
+                        let area = completion_area(textwindow[1], completion);
+                        let (list, mut state) = render_completions(completion);
+                        rect.render_widget(Clear, area);
+                        rect.render_stateful_widget(list, area, &mut state);
+                    }
                 }
+
+                MenuItem::Mentions => {
+                    let (list, mut state) = render_mentions(
+                        &account.mentions,
+                        &account.chat_rooms,
+                        account.selected_mention,
+                        &TimelineStyle {
+                            display: &config.display,
+                            username: username.as_deref().unwrap_or_default(),
+                            keywords: &config.alerts.keywords,
+                            width: chunks[1].width.saturating_sub(2),
+                            unread_after: None,
+                        },
+                    );
+                    rect.render_stateful_widget(list, chunks[1], &mut state);
+                }
+            }
+
+            if show_log {
+                let area = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+                    .split(chunks[1])[1];
+                let panel = log.with_lines(|lines| render_debug_log(lines, area.height));
+                rect.render_widget(Clear, area);
+                rect.render_widget(panel, area);
             }
         })?;
 
+        let event = match rx.recv()? {
+            Event::Input(event) => event,
+            Event::Quit => break,
+            Event::Tick => {
+                if status.as_ref().is_some_and(Status::expired) {
+                    status = None;
+                }
+                // Held back while logged out; they'd only be turned away
+                let due = match username {
+                    Some(_) => account.outbox.due(),
+                    None => Vec::new(),
+                };
+                for message in due {
+                    send_outgoing(message, &net)?;
+                }
+                continue;
+            }
+            Event::LoggedIn {
+                username: name,
+                offline,
+            } => {
+                login_form.pending = false;
+                connection = Connection {
+                    unreachable: offline.clone(),
+                    ..Connection::default()
+                };
+
+                // Back after the session expired: everything on screen still stands
+                if let Some((_, tab)) = signed_out.take().filter(|(previous, _)| *previous == name)
+                {
+                    username = Some(name);
+                    active_menu_item = tab;
+                    login_form.reset_password();
+                    catch_up(&mut account, &net)?;
+                    continue;
+                }
+
+                // Someone else, so nothing of whoever was here before carries over
+                account = Account::default();
+                textarea.select_all();
+                textarea.cut();
+
+                let dir = config::account_dir(config.server(), &name);
+                if let Err(err) = config.use_account(Some(&dir)) {
+                    status = Some(Status::error(format!("Account settings: {err}")));
+                }
+                accounts = session::accounts(config.server());
+                selected_account = Some(name.clone());
+                account.read_state = ReadState::load(dir.join("read.json")).unwrap_or_else(|err| {
+                    status = Some(Status::error(format!(
+                        "Couldn't load what's been read: {err}"
+                    )));
+                    ReadState::default()
+                });
+                account.outbox = Outbox::load(dir.join("outbox.json")).unwrap_or_else(|err| {
+                    status = Some(Status::error(format!(
+                        "Couldn't load unsent messages: {err}"
+                    )));
+                    Outbox::default()
+                });
+                username = Some(name);
+
+                // Show the rooms as last seen while the server catches up
+                account.cache = MessageCache::open(dir);
+                account.chat_rooms = account.cache.rooms();
+                account.selected_room = account.chat_rooms.first().cloned();
+                if let Some(room) = &account.selected_room {
+                    open_room(room.id, &mut account.cache, &mut account.chat, &net)?;
+                }
+
+                match offline {
+                    Some(err) => {
+                        status = Some(Status::error(format!(
+                            "Offline, showing saved messages: {err}"
+                        )));
+                    }
+                    None => {
+                        net.send(Command::GetRooms)?;
+                        net.send(Command::GetFriends)?;
+                    }
+                }
+                continue;
+            }
+            Event::ServerInfo(info) => {
+                account.max_message_length = info.max_message_length;
+                continue;
+            }
+            Event::Renamed(Ok(name)) => {
+                status = Some(Status::info(format!("You are now known as {name}")));
+                // Everything kept for the account goes along with its name,
+                // once all that's pending has gone to where it was
+                let previous = username.clone().unwrap_or_default();
+                let flushed = account
+                    .read_state
+                    .save()
+                    .and_then(|()| account.outbox.save());
+                match flushed.and_then(|()| session::rename(config.server(), &previous, &name)) {
+                    Ok(()) => {
+                        let dir = config::account_dir(config.server(), &name);
+                        account.read_state.set_path(dir.join("read.json"));
+                        account.outbox.set_path(dir.join("outbox.json"));
+                        account.cache = MessageCache::open(dir.clone());
+                        if let Err(err) = config.use_account(Some(&dir)) {
+                            status = Some(Status::error(format!("Account settings: {err}")));
+                        }
+                    }
+                    // Kept where it is for now, rather than lose track of it
+                    Err(err) => {
+                        status = Some(Status::error(format!(
+                            "You are now known as {name}, but what's saved for {previous} \
+                             couldn't be moved: {err}"
+                        )));
+                    }
+                }
+                accounts = session::accounts(config.server());
+                selected_account = Some(name.clone());
+                username = Some(name);
+                continue;
+            }
+            Event::Renamed(Err(err)) => {
+                status = Some(Status::error(format!("Couldn't change name: {err}")));
+                continue;
+            }
+            Event::LoginFailed(err) => {
+                login_form.pending = false;
+                login_form.error = Some(err);
+                login_form.reset_password();
+                continue;
+            }
+            Event::LoggedOut(result) => {
+                status = Some(match result {
+                    Ok(()) => Status::info("Logged out"),
+                    Err(err) => Status::error(format!(
+                        "Logged out, but couldn't clear the saved session: {err}"
+                    )),
+                });
+                if let Err(err) = config.use_account(None) {
+                    status = Some(Status::error(format!("Settings: {err}")));
+                }
+
+                // Ready to log back in, unless another account was picked
+                let previous = username.take();
+                match switch_to.take() {
+                    Some(saved) => pick_account(&saved, config.server(), &mut login_form, &net)?,
+                    None => {
+                        login_form.set_username(previous.as_deref().unwrap_or_default());
+                        login_form.register = false;
+                        login_form.reset_password();
+                    }
+                }
+
+                // Nothing of the last account carries over to the next
+                mode = Mode::Normal;
+                active_menu_item = MenuItem::Home;
+                textarea.select_all();
+                textarea.cut();
+                account = Account::default();
+                signed_out = None;
+                connection = Connection::default();
+
+                accounts = session::accounts(config.server());
+                continue;
+            }
+            Event::SessionNotSaved(err) => {
+                status = Some(Status::error(format!(
+                    "Couldn't save the session for next time: {err}"
+                )));
+                continue;
+            }
+            Event::SessionExpired => {
+                if let Some(name) = &username {
+                    // A stale session would only fail again next run
+                    let _ = session::forget(config.server(), name);
+                }
+                accounts = session::accounts(config.server());
+                if let Some(name) = username.take() {
+                    login_form.set_username(&name);
+                    login_form.reset_password();
+                    login_form.register = false;
+                    login_form.error =
+                        Some("Your session has expired, please log in again".to_string());
+                    signed_out = Some((name, active_menu_item));
+                    active_menu_item = MenuItem::Home;
+                    mode = Mode::Normal;
+                }
+                continue;
+            }
+            Event::Rooms(Ok(rooms)) => {
+                account.chat_rooms = rooms;
+                account.rooms_error = None;
+                account.cache.set_rooms(&account.chat_rooms);
+
+                // Keep the same room selected if it's still there, otherwise fall back to the first
+                let previous = account.selected_room.as_ref().map(|room| room.id);
+                account.selected_room = previous
+                    .and_then(|id| account.chat_rooms.iter().find(|room| room.id == id))
+                    .or(account.chat_rooms.first())
+                    .cloned();
+                let current = account.selected_room.as_ref().map(|room| room.id);
+                if current != previous {
+                    account.chat.clear();
+                    if let Some(room_id) = current {
+                        open_room(room_id, &mut account.cache, &mut account.chat, &net)?;
+                    }
+                }
+
+                // Catch up on what was missed since the last run; replies for
+                // rooms that aren't selected only go to the cache, mentions
+                // and unread counts
+                for room in account.chat_rooms.iter().filter(|room| room.joined) {
+                    if account.scanned_rooms.insert(room.id) && Some(room.id) != current {
+                        net.send(Command::GetMessages {
+                            room_id: room.id,
+                            since_id: account.cache.newest(room.id),
+                        })?;
+                    }
+                }
+                continue;
+            }
+            Event::Rooms(Err(err)) | Event::RoomActionFailed(err) => {
+                account.rooms_error = Some(err.to_string());
+                continue;
+            }
+            Event::RoomCreated(room) => {
+                // The refreshed list that follows keeps this selection
+                account.chat_error = None;
+                open_room(room.id, &mut account.cache, &mut account.chat, &net)?;
+                account.selected_room = Some(room);
+                continue;
+            }
+            Event::RoomsChanged => {
+                net.send(Command::GetRooms)?;
+                continue;
+            }
+            Event::Messages {
+                room_id,
+                since_id,
+                messages,
+            } => {
+                if let Ok(messages) = &messages {
+                    account.cache.add(room_id, messages);
+                    account.outbox.arrived(
+                        &Conversation::Room(room_id),
+                        messages.iter().map(|message| {
+                            (message.client_id.as_deref(), message.message_text.as_str())
+                        }),
+                    );
+                }
+                if let (Ok(messages), Some(name)) = (&messages, &username) {
+                    let conversation = Conversation::Room(room_id);
+                    if account.selected_room.as_ref().map(|room| room.id) == Some(room_id)
+                        && account.room_divider.map(|(id, _)| id) != Some(room_id)
+                    {
+                        account.room_divider =
+                            Some((room_id, account.read_state.last_read(&conversation)));
+                    }
+                    let ids = messages
+                        .iter()
+                        .map(|message| (message.id, message.message_user_name == *name));
+                    account.read_state.seen(&conversation, ids);
+                    for message in messages {
+                        note_mention(
+                            &mut account.mentions,
+                            message,
+                            name,
+                            &config.alerts.keywords,
+                        );
+                    }
+                }
+
+                // Drop replies for a room that is no longer selected
+                if account.selected_room.as_ref().map(|room| room.id) == Some(room_id) {
+                    match messages {
+                        Ok(messages) => {
+                            let scroll = account.scrollbacks.entry(room_id).or_default();
+                            if since_id.is_some() {
+                                // Only what's newer than the cached history on screen
+                                for message in messages {
+                                    if !account.chat.iter().any(|known| known.id == message.id) {
+                                        scroll.message_added();
+                                        account.chat.push(message);
+                                    }
+                                }
+                            } else {
+                                scroll.loading = false;
+                                scroll.reached_start = messages.len() < PAGE_SIZE;
+                                // A remembered position may be further back than this page reaches
+                                scroll.up(0, messages.len());
+                                account.chat = messages;
+                            }
+                            account.chat_error = None;
+                            if let Some(target) = account.jump_to.take() {
+                                match seek_message(target, room_id, &account.chat, scroll) {
+                                    Ok(Some(older)) => {
+                                        account.jump_to = Some(target);
+                                        net.send(older)?;
+                                    }
+                                    Ok(None) => {}
+                                    Err(err) => status = Some(Status::error(err)),
+                                }
+                            }
+                            if matches!(active_menu_item, MenuItem::ChatRooms) {
+                                let since_id = account
+                                    .chat
+                                    .iter()
+                                    .map(|message| message.id)
+                                    .max()
+                                    .unwrap_or(0);
+                                net.send(Command::Watch {
+                                    conversation: Conversation::Room(room_id),
+                                    since_id,
+                                })?;
+                            }
+                        }
+                        // Whatever was cached stays on screen
+                        Err(err) if !account.chat.is_empty() => {
+                            account.chat_error = Some(format!("Showing saved messages: {err}"));
+                        }
+                        Err(err) => account.chat_error = Some(err.to_string()),
+                    }
+                }
+                continue;
+            }
+            Event::OlderMessages { room_id, messages } => {
+                let scroll = account.scrollbacks.entry(room_id).or_default();
+                scroll.loading = false;
+                if account.selected_room.as_ref().map(|room| room.id) == Some(room_id) {
+                    match messages {
+                        Ok(mut older) => {
+                            account.cache.add(room_id, &older);
+                            scroll.reached_start = older.len() < PAGE_SIZE;
+                            // Offsets count from the bottom, so the view stays put
+                            older.retain(|message| {
+                                !account.chat.iter().any(|known| known.id == message.id)
+                            });
+                            if let Some(name) = &username {
+                                for message in &older {
+                                    note_mention(
+                                        &mut account.mentions,
+                                        message,
+                                        name,
+                                        &config.alerts.keywords,
+                                    );
+                                }
+                            }
+                            older.append(&mut account.chat);
+                            account.chat = older;
+                            if let Some(target) = account.jump_to.take() {
+                                match seek_message(target, room_id, &account.chat, scroll) {
+                                    Ok(Some(older)) => {
+                                        account.jump_to = Some(target);
+                                        net.send(older)?;
+                                    }
+                                    Ok(None) => {}
+                                    Err(err) => status = Some(Status::error(err)),
+                                }
+                            }
+                        }
+                        Err(err) => account.chat_error = Some(err.to_string()),
+                    }
+                }
+                continue;
+            }
+            Event::DirectMessages { friend, messages } => {
+                if let (Ok(messages), Some(name)) = (&messages, &username) {
+                    let conversation = Conversation::Direct(friend.clone());
+                    account.outbox.arrived(
+                        &conversation,
+                        messages
+                            .iter()
+                            .filter(|message| message.sender_user_name == *name)
+                            .map(|message| {
+                                (message.client_id.as_deref(), message.message_text.as_str())
+                            }),
+                    );
+                    if account.selected_friend.as_ref() == Some(&friend)
+                        && account.dm_divider.as_ref().map(|(partner, _)| partner) != Some(&friend)
+                    {
+                        account.dm_divider =
+                            Some((friend.clone(), account.read_state.last_read(&conversation)));
+                    }
+                    let ids = messages
+                        .iter()
+                        .map(|message| (message.id, message.sender_user_name == *name));
+                    account.read_state.seen(&conversation, ids);
+                }
+
+                if account.selected_friend.as_deref() == Some(friend.as_str()) {
+                    match messages {
+                        Ok(messages) => {
+                            account.dm_chat = messages;
+                            account.dm_error = None;
+                            if matches!(active_menu_item, MenuItem::FriendsList) {
+                                let since_id = account
+                                    .dm_chat
+                                    .iter()
+                                    .map(|message| message.id)
+                                    .max()
+                                    .unwrap_or(0);
+                                net.send(Command::Watch {
+                                    conversation: Conversation::Direct(friend),
+                                    since_id,
+                                })?;
+                            }
+                        }
+                        Err(err) => account.dm_error = Some(err.to_string()),
+                    }
+                }
+                continue;
+            }
+            Event::NewDirectMessage(message) => {
+                if let Some(name) = &username {
+                    let conversation = Conversation::Direct(message.partner(name).to_string());
+                    let ours = message.sender_user_name == *name;
+                    account.read_state.seen(&conversation, [(message.id, ours)]);
+                    if ours {
+                        let text = message.message_text.as_str();
+                        account
+                            .outbox
+                            .arrived(&conversation, [(message.client_id.as_deref(), text)]);
+                    }
+                }
+                let in_selected_chat = username.as_deref().is_some_and(|name| {
+                    account.selected_friend.as_deref() == Some(message.partner(name))
+                });
+                if in_selected_chat && !account.dm_chat.iter().any(|known| known.id == message.id) {
+                    account.dm_chat.push(message);
+                }
+                continue;
+            }
+            Event::Friends(Ok(list)) => {
+                account.friends = list;
+                account.friends_error = None;
+
+                let previous = account.selected_friend.clone();
+                account.selected_friend = previous
+                    .clone()
+                    .filter(|friend| account.friends.contains(friend))
+                    .or_else(|| account.friends.first().cloned());
+                if account.selected_friend != previous {
+                    account.dm_chat.clear();
+                    account.dm_error = None;
+                    if let Some(friend) = &account.selected_friend {
+                        net.send(Command::GetDirectMessages {
+                            friend: friend.clone(),
+                        })?;
+                    }
+                }
+
+                // Catch up on private messages from before we started
+                for friend in &account.friends {
+                    if account.scanned_friends.insert(friend.clone())
+                        && Some(friend) != account.selected_friend.as_ref()
+                    {
+                        net.send(Command::GetDirectMessages {
+                            friend: friend.clone(),
+                        })?;
+                    }
+                }
+                continue;
+            }
+            Event::FriendRequests(Ok(requests)) => {
+                account.friend_requests = requests;
+                let incoming: Vec<i64> = account
+                    .friend_requests
+                    .iter()
+                    .filter(|request| Some(&request.to_user_name) == username.as_ref())
+                    .map(|request| request.id)
+                    .collect();
+                account.selected_request = account
+                    .selected_request
+                    .filter(|id| incoming.contains(id))
+                    .or(incoming.first().copied());
+                continue;
+            }
+            Event::Friends(Err(err))
+            | Event::FriendRequests(Err(err))
+            | Event::FriendActionFailed(err) => {
+                account.friends_error = Some(err.to_string());
+                continue;
+            }
+            Event::FriendsChanged => {
+                net.send(Command::GetFriends)?;
+                continue;
+            }
+            Event::Sent(client_id) => {
+                account.outbox.sent(&client_id);
+                continue;
+            }
+            Event::SendFailed { client_id, error } => {
+                account.outbox.failed(&client_id, &error);
+                continue;
+            }
+            Event::PushConnected => {
+                connection.push = true;
+                connection.push_error = None;
+                continue;
+            }
+            Event::PushLost(err) => {
+                connection.push = false;
+                connection.push_error = Some(err);
+                continue;
+            }
+            Event::Heartbeat(result) => {
+                match result {
+                    Ok(latency) => {
+                        connection.latency = Some(latency);
+                        // Back after being offline, perhaps since logging in
+                        if connection.unreachable.take().is_some() && username.is_some() {
+                            status = Some(Status::info("Back online"));
+                            catch_up(&mut account, &net)?;
+                        }
+                    }
+                    Err(err) => connection.unreachable = Some(err.to_string()),
+                }
+                continue;
+            }
+            Event::NewMessage(message) => {
+                if let Some(name) = &username {
+                    let conversation = Conversation::Room(message.message_room_id);
+                    let ours = message.message_user_name == *name;
+                    account.read_state.seen(&conversation, [(message.id, ours)]);
+                    if ours {
+                        let text = message.message_text.as_str();
+                        account
+                            .outbox
+                            .arrived(&conversation, [(message.client_id.as_deref(), text)]);
+                    }
+                    let mentioned = note_mention(
+                        &mut account.mentions,
+                        &message,
+                        name,
+                        &config.alerts.keywords,
+                    );
+                    if mentioned && config.alerts.bell {
+                        let backend = terminal.backend_mut();
+                        backend.write_all(b"\x07")?;
+                        Write::flush(backend)?;
+                    }
+                }
+
+                let in_selected_room = account
+                    .selected_room
+                    .as_ref()
+                    .is_some_and(|room| room.id == message.message_room_id);
+                if in_selected_room && !account.chat.iter().any(|known| known.id == message.id) {
+                    account
+                        .scrollbacks
+                        .entry(message.message_room_id)
+                        .or_default()
+                        .message_added();
+                    account.chat.push(message);
+                }
+                continue;
+            }
+        };
+
+        if event.code == KeyCode::Char('c') && event.modifiers.contains(KeyModifiers::CONTROL) {
+            break;
+        }
+        if event.code == KeyCode::Char('z') && event.modifiers.contains(KeyModifiers::CONTROL) {
+            screen::suspend()?;
+            // Whatever ran in the meantime drew over the screen
+            terminal.clear()?;
+            continue;
+        }
+        if event.code == KeyCode::F(12) {
+            show_log = !show_log;
+            continue;
+        }
+
+        // Until someone has logged in, every key belongs to the login form
+        if username.is_none() {
+            if event.code == KeyCode::Char('s') && event.modifiers.contains(KeyModifiers::CONTROL) {
+                config.next_profile();
+                accounts = session::accounts(config.server());
+                continue;
+            }
+            // Fills in the next saved account
+            if event.code == KeyCode::Char('a') && event.modifiers.contains(KeyModifiers::CONTROL) {
+                let typed = login_form.username.lines()[0].trim().to_string();
+                if let Some(saved) = step_selection(&accounts, |saved| saved.username == typed, 1)
+                    .filter(|next| next.username != typed)
+                    .or(accounts.first().cloned())
+                {
+                    login_form.set_username(&saved.username);
+                    login_form.focus = LoginField::Username;
+                }
+                continue;
+            }
+            // A saved account that is still logged in doesn't need its password
+            if event.code == KeyCode::Enter
+                && login_form.focus == LoginField::Username
+                && !login_form.register
+                && !login_form.pending
+            {
+                let typed = login_form.username.lines()[0].trim();
+                let saved = accounts
+                    .iter()
+                    .find(|saved| saved.resumable() && saved.username == typed);
+                if let Some(saved) = saved {
+                    pick_account(saved, config.server(), &mut login_form, &net)?;
+                    continue;
+                }
+            }
+
+            if let LoginAction::Submit {
+                username: name,
+                pwd,
+                register,
+            } = login_form.handle_input(event.into())
+            {
+                login_form.pending = true;
+                net.send(Command::Login {
+                    server: config.server().clone(),
+                    username: name,
+                    credentials: Credentials::Password { pwd, register },
+                })?;
+            }
+            continue;
+        }
+
+        // A second 'r' confirms removing a friend; any other key cancels it
+        let confirming_remove = std::mem::take(&mut account.confirm_remove);
+        // Any key but Tab settles on the completion in the input box
+        if event.code != KeyCode::Tab {
+            account.completion = None;
+        }
+
         // Keyboard Navigation
         match mode {
-            Mode::Normal => match rx.recv()? {
-                Event::Input(event) => match event.code {
-                    KeyCode::Char('q') => {
-                        disable_raw_mode()?;
-                        execute!(
-                            terminal.backend_mut(),
-                            LeaveAlternateScreen,
-                            DisableMouseCapture
-                        )?;
-                        terminal.show_cursor()?;
-                        break;
-                    }
-
-                    KeyCode::Char('h') => active_menu_item = MenuItem::Home,
-                    KeyCode::Char('c') => active_menu_item = MenuItem::ChatRooms,
-                    KeyCode::Char('f') => active_menu_item = MenuItem::FriendsList,
-                    KeyCode::Char('i') => mode = Mode::Insert,
-
-                    KeyCode::Down => {
-                        if let Some(selected) = room_list_state.selected() {
-                            if selected < chat_rooms.len() - 1 {
-                                room_list_state.select(Some(selected + 1));
+            Mode::Normal => match event.code {
+                KeyCode::Char('q') => break,
+
+                KeyCode::Char('h') => active_menu_item = MenuItem::Home,
+                // Switching tabs re-fetches the conversation there, which also
+                // points the live subscription at it
+                KeyCode::Char('c') => {
+                    active_menu_item = MenuItem::ChatRooms;
+                    if let Some(room) = &account.selected_room {
+                        open_room(room.id, &mut account.cache, &mut account.chat, &net)?;
+                    }
+                }
+                KeyCode::Char('f') => {
+                    active_menu_item = MenuItem::FriendsList;
+                    net.send(Command::GetFriends)?;
+                    if let Some(friend) = &account.selected_friend {
+                        net.send(Command::GetDirectMessages {
+                            friend: friend.clone(),
+                        })?;
+                    }
+                }
+                KeyCode::Char('m') => {
+                    active_menu_item = MenuItem::Mentions;
+                    account.selected_mention = account
+                        .selected_mention
+                        .or(account.mentions.last().map(|message| message.id));
+                }
+                KeyCode::Char('i')
+                    if matches!(
+                        active_menu_item,
+                        MenuItem::ChatRooms | MenuItem::FriendsList
+                    ) =>
+                {
+                    mode = Mode::Insert;
+                }
+                // Retry or throw away messages that couldn't be sent
+                KeyCode::Char('s') | KeyCode::Char('x') => {
+                    let conversation = match active_menu_item {
+                        MenuItem::ChatRooms => account
+                            .selected_room
+                            .as_ref()
+                            .map(|room| Conversation::Room(room.id)),
+                        MenuItem::FriendsList => {
+                            account.selected_friend.clone().map(Conversation::Direct)
+                        }
+                        _ => None,
+                    };
+                    if let Some(conversation) = conversation {
+                        if event.code == KeyCode::Char('s') {
+                            account.outbox.retry(&conversation);
+                        } else {
+                            let discarded = account.outbox.discard(&conversation);
+                            if discarded > 0 {
+                                status = Some(Status::info(format!(
+                                    "Discarded {discarded} unsent message(s)"
+                                )));
                             }
                         }
-                        if let Some(selected) = friends_list_state.selected() {
-                            if let Ok(user) = read_db() {
-                                let friends_list = user.friends.len();
-                                if selected < friends_list - 1 {
-                                    friends_list_state.select(Some(selected + 1));
-                                }
+                    }
+                }
+
+                // Chat Rooms tab options
+                KeyCode::Char('n') if matches!(active_menu_item, MenuItem::ChatRooms) => {
+                    account.prompt = Some(Prompt::CreateRoom);
+                    mode = Mode::Insert;
+                }
+                KeyCode::Char('e') if matches!(active_menu_item, MenuItem::ChatRooms) => {
+                    if let Some(room) = &account.selected_room {
+                        // Start from the current title so small fixes are easy
+                        textarea.insert_str(&room.title);
+                        account.prompt = Some(Prompt::RenameRoom(room.id));
+                        mode = Mode::Insert;
+                    }
+                }
+                KeyCode::Char('j') | KeyCode::Char('l')
+                    if matches!(active_menu_item, MenuItem::ChatRooms) =>
+                {
+                    if let Some(room) = &account.selected_room {
+                        net.send(if event.code == KeyCode::Char('j') {
+                            Command::JoinRoom { room_id: room.id }
+                        } else {
+                            Command::LeaveRoom { room_id: room.id }
+                        })?;
+                    }
+                }
+                KeyCode::Char('r') if matches!(active_menu_item, MenuItem::ChatRooms) => {
+                    net.send(Command::GetRooms)?;
+                }
+                KeyCode::PageUp | KeyCode::PageDown | KeyCode::Home | KeyCode::End
+                    if matches!(active_menu_item, MenuItem::ChatRooms) =>
+                {
+                    if let Some(room) = &account.selected_room {
+                        let scroll = account.scrollbacks.entry(room.id).or_default();
+                        match event.code {
+                            KeyCode::PageUp => scroll.up(chat_page, account.chat.len()),
+                            KeyCode::PageDown => scroll.down(chat_page),
+                            KeyCode::Home => scroll.up(account.chat.len(), account.chat.len()),
+                            _ => scroll.down(scroll.offset),
+                        }
+
+                        // Fetch the page before the oldest one loaded as the top comes into view
+                        if let Some(oldest) = account.chat.first() {
+                            if scroll.wants_older(chat_page, account.chat.len()) {
+                                scroll.loading = true;
+                                net.send(Command::GetOlderMessages {
+                                    room_id: room.id,
+                                    before_id: oldest.id,
+                                })?;
                             }
                         }
                     }
+                }
+
+                // Home tab options
+                KeyCode::Char('o') if matches!(active_menu_item, MenuItem::Home) => {
+                    net.send(Command::Logout { forget: true })?;
+                }
+                KeyCode::Enter if matches!(active_menu_item, MenuItem::Home) => {
+                    let picked = accounts.iter().find(|saved| {
+                        Some(&saved.username) == selected_account.as_ref()
+                            && selected_account != username
+                    });
+                    if let Some(saved) = picked {
+                        switch_to = Some(saved.clone());
+                        net.send(Command::Logout { forget: false })?;
+                    }
+                }
 
-                    KeyCode::Up => {
-                        if let Some(selected) = room_list_state.selected() {
-                            if selected > 0 {
-                                room_list_state.select(Some(selected - 1));
+                // Mentions tab options
+                KeyCode::Enter if matches!(active_menu_item, MenuItem::Mentions) => {
+                    let mention = account
+                        .mentions
+                        .iter()
+                        .find(|message| Some(message.id) == account.selected_mention);
+                    if let Some(message) = mention {
+                        let room = account
+                            .chat_rooms
+                            .iter()
+                            .find(|room| room.id == message.message_room_id);
+                        match room {
+                            Some(room) => {
+                                account.chat_error = None;
+                                account.jump_to = Some(message.id);
+                                open_room(room.id, &mut account.cache, &mut account.chat, &net)?;
+                                account.selected_room = Some(room.clone());
+                                active_menu_item = MenuItem::ChatRooms;
+                            }
+                            None => {
+                                status = Some(Status::error("That room is no longer listed"));
                             }
                         }
-                        if let Some(selected) = friends_list_state.selected() {
-                            if selected > 0 {
-                                friends_list_state.select(Some(selected - 1));
+                    }
+                }
+
+                // Friends tab options
+                KeyCode::Char('a') if matches!(active_menu_item, MenuItem::FriendsList) => {
+                    account.prompt = Some(Prompt::AddFriend);
+                    mode = Mode::Insert;
+                }
+                KeyCode::Char('r') if matches!(active_menu_item, MenuItem::FriendsList) => {
+                    if let Some(friend) = &account.selected_friend {
+                        if confirming_remove {
+                            net.send(Command::RemoveFriend {
+                                username: friend.clone(),
+                            })?;
+                        } else {
+                            account.confirm_remove = true;
+                        }
+                    }
+                }
+                KeyCode::Char('y') | KeyCode::Char('n')
+                    if matches!(active_menu_item, MenuItem::FriendsList) =>
+                {
+                    if let Some(id) = account.selected_request {
+                        net.send(if event.code == KeyCode::Char('y') {
+                            Command::AcceptFriendRequest { id }
+                        } else {
+                            Command::DeclineFriendRequest { id }
+                        })?;
+                    }
+                }
+                KeyCode::Tab if matches!(active_menu_item, MenuItem::FriendsList) => {
+                    let incoming: Vec<i64> = account
+                        .friend_requests
+                        .iter()
+                        .filter(|request| Some(&request.to_user_name) == username.as_ref())
+                        .map(|request| request.id)
+                        .collect();
+                    account.selected_request =
+                        step_selection(&incoming, |id| Some(*id) == account.selected_request, 1)
+                            .filter(|next| Some(*next) != account.selected_request)
+                            .or(incoming.first().copied());
+                }
+
+                KeyCode::Down | KeyCode::Up => {
+                    let step = if event.code == KeyCode::Down { 1 } else { -1 };
+                    match active_menu_item {
+                        MenuItem::ChatRooms => {
+                            let current = account.selected_room.as_ref().map(|room| room.id);
+                            let next = step_selection(
+                                &account.chat_rooms,
+                                |room| Some(room.id) == current,
+                                step,
+                            );
+                            if let Some(room) = next.filter(|next| Some(next.id) != current) {
+                                account.chat_error = None;
+                                open_room(room.id, &mut account.cache, &mut account.chat, &net)?;
+                                account.selected_room = Some(room);
                             }
                         }
+                        MenuItem::FriendsList => {
+                            let next = step_selection(
+                                &account.friends,
+                                |friend| Some(friend) == account.selected_friend.as_ref(),
+                                step,
+                            );
+                            if let Some(friend) =
+                                next.filter(|next| Some(next) != account.selected_friend.as_ref())
+                            {
+                                account.dm_chat.clear();
+                                account.dm_error = None;
+                                net.send(Command::GetDirectMessages {
+                                    friend: friend.clone(),
+                                })?;
+                                account.selected_friend = Some(friend);
+                            }
+                        }
+                        MenuItem::Mentions => {
+                            // Listed newest first, so down goes back in time
+                            let next = step_selection(
+                                &account.mentions,
+                                |message| Some(message.id) == account.selected_mention,
+                                -step,
+                            );
+                            account.selected_mention = next.map(|message| message.id);
+                        }
+                        MenuItem::Home => {
+                            let next = step_selection(
+                                &accounts,
+                                |saved| Some(&saved.username) == selected_account.as_ref(),
+                                step,
+                            );
+                            selected_account = next.map(|saved| saved.username);
+                        }
                     }
-                    _ => {}
-                },
-                Event::Tick => {}
+                }
+                _ => {}
             },
 
-            Mode::Insert => match crossterm::event::read()?.into() {
-                Input { key: Key::Esc, .. } => mode = Mode::Normal,
-                input => {
-                    textarea.input_without_shortcuts(input.clone());
-                    if input.key == Key::Enter {
-                        let to_send = textarea.clone().into_lines();
+            Mode::Insert => match event.into() {
+                Input { key: Key::Esc, .. } => {
+                    // Whatever was typed for a prompt shouldn't turn into a message
+                    if account.prompt.take().is_some() {
                         textarea.select_all();
                         textarea.delete_line_by_head();
-                        if let Some(selected) = room_list_state.selected() {
-                            let room_id = selected as i64; // Cast usize to i64
-                            tokio::runtime::Runtime::new()
-                                .unwrap()
-                                .block_on(async { send_message(&client, room_id, to_send).await });
+                    }
+                    mode = Mode::Normal;
+                }
+                // Ctrl+J is also what Shift+Enter arrives as on many terminals
+                Input {
+                    key: Key::Enter,
+                    shift: true,
+                    ..
+                }
+                | Input {
+                    key: Key::Enter,
+                    alt: true,
+                    ..
+                }
+                | Input {
+                    key: Key::Char('j'),
+                    ctrl: true,
+                    ..
+                } if account.prompt.is_none() => {
+                    textarea.insert_newline();
+                }
+                Input {
+                    key: Key::Enter, ..
+                } => {
+                    // Too long to send: keep it so it can be cut down
+                    let length = message_length(&textarea);
+                    if account.prompt.is_none()
+                        && account.max_message_length.is_some_and(|max| length > max)
+                    {
+                        let error = Some(format!(
+                            "Message is {length} characters; the server allows {}",
+                            account.max_message_length.unwrap_or_default()
+                        ));
+                        match active_menu_item {
+                            MenuItem::FriendsList => account.dm_error = error,
+                            _ => account.chat_error = error,
+                        }
+                        continue;
+                    }
+
+                    // A mistyped command stays in the box to be fixed
+                    let command = match account.prompt {
+                        None => commands::parse(&textarea.lines().join("\n")),
+                        Some(_) => None,
+                    };
+                    if let Some(Err(err)) = command {
+                        status = Some(Status::error(err));
+                        continue;
+                    }
+
+                    let mut to_send = textarea.clone().into_lines();
+                    textarea.select_all();
+                    textarea.delete_line_by_head();
+
+                    if let Some(prompt) = account.prompt.take() {
+                        let answer = to_send.join(" ").trim().to_string();
+                        match prompt {
+                            _ if answer.is_empty() => {}
+                            Prompt::AddFriend => {
+                                net.send(Command::SendFriendRequest { username: answer })?;
+                            }
+                            Prompt::CreateRoom => {
+                                net.send(Command::CreateRoom { title: answer })?;
+                            }
+                            Prompt::RenameRoom(room_id) => {
+                                net.send(Command::RenameRoom {
+                                    room_id,
+                                    title: answer,
+                                })?;
+                            }
                         }
                         mode = Mode::Normal;
+                        continue;
+                    }
+
+                    match command {
+                        // An action is sent like any other message
+                        Some(Ok(SlashCommand::Me(action))) => {
+                            to_send = vec![format!("/me {action}")]
+                        }
+                        Some(Ok(command)) => {
+                            mode = Mode::Normal;
+                            let in_room = matches!(active_menu_item, MenuItem::ChatRooms);
+                            match command {
+                                SlashCommand::Join(name) => {
+                                    active_menu_item = MenuItem::ChatRooms;
+                                    let wanted = account
+                                        .chat_rooms
+                                        .iter()
+                                        .find(|room| {
+                                            room.title.eq_ignore_ascii_case(&name)
+                                                || room.id.to_string() == name
+                                        })
+                                        .cloned();
+                                    match wanted {
+                                        Some(room) => {
+                                            if !room.joined {
+                                                net.send(Command::JoinRoom { room_id: room.id })?;
+                                            }
+                                            if account.selected_room.as_ref().map(|room| room.id)
+                                                != Some(room.id)
+                                            {
+                                                account.chat_error = None;
+                                            }
+                                            // Also points the live subscription at it
+                                            open_room(
+                                                room.id,
+                                                &mut account.cache,
+                                                &mut account.chat,
+                                                &net,
+                                            )?;
+                                            status = Some(Status::info(format!(
+                                                "Joined {}",
+                                                room.title
+                                            )));
+                                            account.selected_room = Some(room);
+                                        }
+                                        None => {
+                                            net.send(Command::CreateRoom {
+                                                title: name.clone(),
+                                            })?;
+                                            status = Some(Status::info(format!("Creating {name}")));
+                                        }
+                                    }
+                                }
+                                SlashCommand::Leave | SlashCommand::Topic(_)
+                                    if !in_room || account.selected_room.is_none() =>
+                                {
+                                    status = Some(Status::error(
+                                        "Select a room in the Chat Rooms tab first",
+                                    ));
+                                }
+                                SlashCommand::Leave => {
+                                    if let Some(room) = &account.selected_room {
+                                        net.send(Command::LeaveRoom { room_id: room.id })?;
+                                        status = Some(Status::info(format!("Left {}", room.title)));
+                                    }
+                                }
+                                SlashCommand::Topic(topic) => {
+                                    if let Some(room) = &account.selected_room {
+                                        net.send(Command::SetRoomTopic {
+                                            room_id: room.id,
+                                            topic,
+                                        })?;
+                                    }
+                                }
+                                SlashCommand::Msg { friend, .. }
+                                    if !account.friends.contains(&friend) =>
+                                {
+                                    status = Some(Status::error(format!(
+                                        "{friend} isn't on your friends list"
+                                    )));
+                                }
+                                SlashCommand::Msg { friend, text } => {
+                                    status = Some(Status::info(format!("Sent to {friend}")));
+                                    let message =
+                                        account.outbox.push(Conversation::Direct(friend), text);
+                                    send_outgoing(message, &net)?;
+                                }
+                                SlashCommand::Nick(name) => {
+                                    net.send(Command::Rename { username: name })?;
+                                }
+                                SlashCommand::Clear => {
+                                    match active_menu_item {
+                                        MenuItem::FriendsList => account.dm_chat.clear(),
+                                        _ => account.chat.clear(),
+                                    }
+                                    status = None;
+                                }
+                                SlashCommand::Logout => {
+                                    net.send(Command::Logout { forget: true })?;
+                                }
+                                SlashCommand::Quit => break,
+                                SlashCommand::Help(name) => {
+                                    status = Some(match commands::help(name.as_deref()) {
+                                        Ok(help) => Status::info(help),
+                                        Err(err) => Status::error(err),
+                                    });
+                                }
+                                SlashCommand::Me(_) => {}
+                            }
+                            continue;
+                        }
+                        _ => {
+                            // "//" sends a message that starts with a slash
+                            if let Some(first) = to_send.first_mut() {
+                                if first.starts_with("//") {
+                                    first.remove(0);
+                                }
+                            }
+                        }
+                    }
+
+                    // Goes to whichever conversation is on screen
+                    let blank = to_send.iter().all(|line| line.trim().is_empty());
+                    match (
+                        &active_menu_item,
+                        &account.selected_room,
+                        &account.selected_friend,
+                    ) {
+                        _ if blank => {}
+                        (MenuItem::ChatRooms, Some(room), _) if !room.joined => {
+                            account.chat_error =
+                                Some("Join this room with 'j' to post in it".to_string());
+                        }
+                        (MenuItem::ChatRooms, Some(room), _) => {
+                            let message = account
+                                .outbox
+                                .push(Conversation::Room(room.id), to_send.join("\n"));
+                            send_outgoing(message, &net)?;
+                        }
+                        (MenuItem::FriendsList, _, Some(friend)) => {
+                            let message = account
+                                .outbox
+                                .push(Conversation::Direct(friend.clone()), to_send.join("\n"));
+                            send_outgoing(message, &net)?;
+                        }
+                        _ => {}
+                    }
+                    mode = Mode::Normal;
+                }
+                Input { key: Key::Tab, .. } if account.prompt.is_none() => {
+                    // Each further Tab swaps in the next match
+                    let replace = if let Some(completion) = &mut account.completion {
+                        Some(completion.advance())
+                    } else {
+                        let (row, col) = textarea.cursor();
+                        let line: String = textarea.lines()[row].chars().take(col).collect();
+                        let me = username.as_deref().unwrap_or_default();
+                        let participants = match active_menu_item {
+                            MenuItem::FriendsList => {
+                                account.selected_friend.iter().map(String::as_str).collect()
+                            }
+                            _ => account
+                                .chat
+                                .iter()
+                                .map(|message| message.message_user_name.as_str())
+                                .filter(|name| *name != me)
+                                .collect(),
+                        };
+                        let sources = Sources {
+                            participants,
+                            friends: &account.friends,
+                            rooms: account
+                                .chat_rooms
+                                .iter()
+                                .map(|room| room.title.as_str())
+                                .collect(),
+                        };
+                        Completion::start(&line, &sources).map(|(typed, started)| {
+                            account.completion = Some(started);
+                            typed
+                        })
+                    };
+
+                    if let (Some(replace), Some(current)) = (replace, &account.completion) {
+                        for _ in 0..replace {
+                            textarea.delete_char();
+                        }
+                        textarea.insert_str(current.current());
                     }
+                    // Nothing to cycle through with only one match
+                    if account
+                        .completion
+                        .as_ref()
+                        .is_some_and(|completion| completion.candidates.len() == 1)
+                    {
+                        account.completion = None;
+                    }
+                }
+                input => {
+                    textarea.input_without_shortcuts(input);
                 }
             },
         }
     }
+
     Ok(())
 }
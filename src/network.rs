@@ -0,0 +1,827 @@
+use crate::cache::MessageCache;
+use crate::config::{account_dir, ServerConfig};
+use crate::interact::{
+    self, create_client_with_cookies, create_user, login, send_direct_message, send_message,
+};
+use crate::rpc::RpcClient;
+use crate::session::{self, SavedSession};
+use crate::structs::{Conversation, Error, Event};
+use crate::subscription::{self, Watched};
+use crossterm::event::KeyEvent;
+use reqwest::cookie::Jar;
+use std::{
+    sync::{mpsc::Sender, Arc},
+    time::{Duration, Instant},
+};
+use tokio::{
+    runtime::Runtime,
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+use tracing::{info, warn};
+
+/// How often the server is checked on while logged in.
+const HEARTBEAT: Duration = Duration::from_secs(15);
+
+/// How a login shows the server who it is.
+pub enum Credentials {
+    Password {
+        pwd: String,
+        register: bool,
+    },
+    /// The session from an earlier run, if the server still accepts it.
+    Saved(SavedSession),
+}
+
+/// Requests the UI thread makes of the network task.
+pub enum Command {
+    /// Starts a session, replacing any there was.
+    Login {
+        server: ServerConfig,
+        username: String,
+        credentials: Credentials,
+    },
+    /// Ends the session. Unless `forget` is false, as when switching to
+    /// another account, the saved session goes too and the next login needs
+    /// the password.
+    Logout {
+        forget: bool,
+    },
+    GetRooms,
+    CreateRoom {
+        title: String,
+    },
+    RenameRoom {
+        room_id: i64,
+        title: String,
+    },
+    SetRoomTopic {
+        room_id: i64,
+        topic: String,
+    },
+    JoinRoom {
+        room_id: i64,
+    },
+    LeaveRoom {
+        room_id: i64,
+    },
+    /// The latest page of a room's history, or with `since_id` everything
+    /// newer than what's cached.
+    GetMessages {
+        room_id: i64,
+        since_id: Option<i64>,
+    },
+    /// The page of history just before `before_id`, for scrolling back.
+    GetOlderMessages {
+        room_id: i64,
+        before_id: i64,
+    },
+    SendMessage {
+        room_id: i64,
+        client_id: String,
+        text: String,
+    },
+    GetDirectMessages {
+        friend: String,
+    },
+    SendDirectMessage {
+        friend: String,
+        client_id: String,
+        text: String,
+    },
+    /// Fetches both the friends list and pending friend requests.
+    GetFriends,
+    SendFriendRequest {
+        username: String,
+    },
+    AcceptFriendRequest {
+        id: i64,
+    },
+    DeclineFriendRequest {
+        id: i64,
+    },
+    RemoveFriend {
+        username: String,
+    },
+    /// Changes our username.
+    Rename {
+        username: String,
+    },
+    /// Follow new messages in `conversation`, starting after `since_id`.
+    Watch {
+        conversation: Conversation,
+        since_id: i64,
+    },
+}
+
+/// Starts the network task on `runtime` and returns the channel used to drive it.
+///
+/// Each command runs as its own task so a slow request never holds up the
+/// others; results come back to the UI thread as [`Event`]s on `events`.
+pub fn spawn(runtime: &Runtime, events: Sender<Event<KeyEvent>>) -> mpsc::UnboundedSender<Command> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    runtime.spawn(async move {
+        // Replaced by a client for whichever server the user logs in to
+        let (client, _) = create_client_with_cookies();
+        let mut rpc = Arc::new(RpcClient::new(client, ServerConfig::default()));
+        let mut username = String::new();
+        let (watch_tx, _) = watch::channel::<Watched>(None);
+        let mut following = Following::default();
+
+        while let Some(command) = rx.recv().await {
+            let events = events.clone();
+
+            match command {
+                Command::Login {
+                    server,
+                    username: name,
+                    credentials,
+                } => {
+                    // Handled inline so nothing else runs against a half-set-up session.
+                    // Each login gets an empty cookie jar, so accounts never share a session
+                    let (client, jar) = create_client_with_cookies();
+                    let login_rpc = Arc::new(RpcClient::new(client, server));
+                    let resuming = matches!(credentials, Credentials::Saved(_));
+                    let result = match credentials {
+                        Credentials::Password { pwd, register } => {
+                            let result = async {
+                                if register {
+                                    create_user(&login_rpc, &name, &pwd).await?;
+                                }
+                                login(&login_rpc, &name, &pwd).await
+                            }
+                            .await;
+                            if result.is_ok() {
+                                if let Err(err) = session::save(&jar, login_rpc.server(), &name) {
+                                    warn!("couldn't save the session: {err}");
+                                    let _ = events.send(Event::SessionNotSaved(err));
+                                }
+                            }
+                            result
+                        }
+                        // Any call will do to find out whether the session still counts
+                        Credentials::Saved(saved) => {
+                            saved.restore(&jar, login_rpc.server());
+                            let result = interact::get_rooms(&login_rpc).await.map(drop);
+                            if let Err(Error::SessionExpired) = result {
+                                let _ = session::forget(login_rpc.server(), &name);
+                            }
+                            result
+                        }
+                    };
+
+                    match result {
+                        Ok(()) => {
+                            info!(server = %login_rpc.server().base_url, "logged in as {name}");
+                            rpc = login_rpc;
+                            username = name.clone();
+
+                            following.start(&rpc, &jar, &name, &watch_tx, &events);
+
+                            let _ = events.send(Event::LoggedIn {
+                                username: name,
+                                offline: None,
+                            });
+                        }
+                        // A session the server let in before can still read what's
+                        // cached. A password can't be checked without the server, so
+                        // logging in with one has to wait for it
+                        Err(err @ Error::NetworkError(_))
+                            if resuming
+                                && MessageCache::exists(&account_dir(
+                                    login_rpc.server(),
+                                    &name,
+                                )) =>
+                        {
+                            warn!("server unreachable, reading {name}'s cache offline: {err}");
+                            rpc = login_rpc;
+                            username = name.clone();
+                            // Notices the server coming back, or turning the session down
+                            following.start(&rpc, &jar, &name, &watch_tx, &events);
+                            let _ = events.send(Event::LoggedIn {
+                                username: name,
+                                offline: Some(err.to_string()),
+                            });
+                        }
+                        Err(err) => {
+                            warn!("login as {name} failed: {err}");
+                            let _ = events.send(Event::LoginFailed(err.to_string()));
+                        }
+                    }
+                }
+
+                Command::Logout { forget } => {
+                    following.stop(&watch_tx);
+                    info!("logged out of {username}");
+                    let result = match forget && !username.is_empty() {
+                        true => session::forget(rpc.server(), &username),
+                        false => Ok(()),
+                    };
+                    username.clear();
+                    let (client, _) = create_client_with_cookies();
+                    rpc = Arc::new(RpcClient::new(client, rpc.server().clone()));
+                    let _ = events.send(Event::LoggedOut(result));
+                }
+
+                Command::GetRooms => {
+                    let rpc = rpc.clone();
+                    tokio::spawn(async move {
+                        let rooms = interact::get_rooms(&rpc).await;
+                        let _ = events.send(Event::Rooms(rooms));
+                    });
+                }
+
+                Command::CreateRoom { title } => {
+                    let rpc = rpc.clone();
+                    tokio::spawn(async move {
+                        let result = interact::create_room(&rpc, &title).await.map(|room| {
+                            let _ = events.send(Event::RoomCreated(room));
+                        });
+                        after_room_action(result, &rpc, &events).await;
+                    });
+                }
+
+                Command::RenameRoom { room_id, title } => {
+                    let rpc = rpc.clone();
+                    tokio::spawn(async move {
+                        let result = interact::rename_room(&rpc, room_id, &title).await;
+                        after_room_action(result, &rpc, &events).await;
+                    });
+                }
+
+                Command::SetRoomTopic { room_id, topic } => {
+                    let rpc = rpc.clone();
+                    tokio::spawn(async move {
+                        let result = interact::set_room_topic(&rpc, room_id, &topic).await;
+                        after_room_action(result, &rpc, &events).await;
+                    });
+                }
+
+                Command::JoinRoom { room_id } => {
+                    let rpc = rpc.clone();
+                    tokio::spawn(async move {
+                        let result = interact::join_room(&rpc, room_id).await;
+                        after_room_action(result, &rpc, &events).await;
+                    });
+                }
+
+                Command::LeaveRoom { room_id } => {
+                    let rpc = rpc.clone();
+                    tokio::spawn(async move {
+                        let result = interact::leave_room(&rpc, room_id).await;
+                        after_room_action(result, &rpc, &events).await;
+                    });
+                }
+
+                Command::GetMessages { room_id, since_id } => {
+                    let rpc = rpc.clone();
+                    tokio::spawn(async move {
+                        let messages = match since_id {
+                            Some(since_id) => {
+                                interact::get_messages_after(&rpc, room_id, since_id).await
+                            }
+                            None => interact::get_messages(&rpc, room_id, None).await,
+                        };
+                        let _ = events.send(Event::Messages {
+                            room_id,
+                            since_id,
+                            messages,
+                        });
+                    });
+                }
+
+                Command::GetOlderMessages { room_id, before_id } => {
+                    let rpc = rpc.clone();
+                    tokio::spawn(async move {
+                        let messages = interact::get_messages(&rpc, room_id, Some(before_id)).await;
+                        let _ = events.send(Event::OlderMessages { room_id, messages });
+                    });
+                }
+
+                Command::SendMessage {
+                    room_id,
+                    client_id,
+                    text,
+                } => {
+                    let rpc = rpc.clone();
+                    let username = username.clone();
+                    tokio::spawn(async move {
+                        // The message itself comes back to us through the subscription
+                        let result =
+                            send_message(&rpc, &username, room_id, &client_id, &text).await;
+                        report_sent(result, client_id, &events);
+                    });
+                }
+
+                Command::GetDirectMessages { friend } => {
+                    let rpc = rpc.clone();
+                    tokio::spawn(async move {
+                        let messages = interact::get_direct_messages(&rpc, &friend).await;
+                        let _ = events.send(Event::DirectMessages { friend, messages });
+                    });
+                }
+
+                Command::SendDirectMessage {
+                    friend,
+                    client_id,
+                    text,
+                } => {
+                    let rpc = rpc.clone();
+                    tokio::spawn(async move {
+                        let result = send_direct_message(&rpc, &friend, &client_id, &text).await;
+                        report_sent(result, client_id, &events);
+                    });
+                }
+
+                Command::GetFriends => {
+                    let rpc = rpc.clone();
+                    tokio::spawn(async move { refresh_friends(&rpc, &events).await });
+                }
+
+                Command::SendFriendRequest { username } => {
+                    let rpc = rpc.clone();
+                    tokio::spawn(async move {
+                        let result = interact::send_friend_request(&rpc, &username).await;
+                        after_friend_action(result, &rpc, &events).await;
+                    });
+                }
+
+                Command::AcceptFriendRequest { id } => {
+                    let rpc = rpc.clone();
+                    tokio::spawn(async move {
+                        let result = interact::accept_friend_request(&rpc, id).await;
+                        after_friend_action(result, &rpc, &events).await;
+                    });
+                }
+
+                Command::DeclineFriendRequest { id } => {
+                    let rpc = rpc.clone();
+                    tokio::spawn(async move {
+                        let result = interact::decline_friend_request(&rpc, id).await;
+                        after_friend_action(result, &rpc, &events).await;
+                    });
+                }
+
+                Command::RemoveFriend { username } => {
+                    let rpc = rpc.clone();
+                    tokio::spawn(async move {
+                        let result = interact::remove_friend(&rpc, &username).await;
+                        after_friend_action(result, &rpc, &events).await;
+                    });
+                }
+
+                Command::Rename { username: name } => {
+                    // Inline, since later messages are sent under the new name
+                    let result = interact::rename_user(&rpc, &name).await;
+                    // What's kept on disk is moved by the UI, which still writes to it
+                    if result.is_ok() {
+                        username = name.clone();
+                    }
+                    let _ = events.send(Event::Renamed(result.map(|()| name)));
+                }
+
+                Command::Watch {
+                    conversation,
+                    since_id,
+                } => {
+                    watch_tx.send_replace(Some((conversation, since_id)));
+                }
+            }
+        }
+    });
+
+    tx
+}
+
+/// The tasks that keep up with the server for as long as a session lasts.
+#[derive(Default)]
+struct Following {
+    push: Option<JoinHandle<()>>,
+    /// Notices when the server stops accepting the session.
+    watchdog: Option<JoinHandle<()>>,
+    heartbeat: Option<JoinHandle<()>>,
+}
+
+impl Following {
+    /// Follows `name`'s session in place of whatever was followed before.
+    /// Runs just the same while the server can't be reached, which is how
+    /// an offline login finds out it's back.
+    fn start(
+        &mut self,
+        rpc: &Arc<RpcClient>,
+        jar: &Arc<Jar>,
+        name: &str,
+        watch_tx: &watch::Sender<Watched>,
+        events: &Sender<Event<KeyEvent>>,
+    ) {
+        self.stop(watch_tx);
+        let beats = tokio::spawn(beat(rpc.clone(), events.clone()));
+        let subscription = tokio::spawn(subscription::run(
+            rpc.clone(),
+            jar.clone(),
+            watch_tx.subscribe(),
+            events.clone(),
+        ));
+        self.watchdog = Some(tokio::spawn({
+            let rpc = rpc.clone();
+            let events = events.clone();
+            let subscription = subscription.abort_handle();
+            let beats = beats.abort_handle();
+            let name = name.to_string();
+            async move {
+                rpc.session_expired().await;
+                warn!("session for {name} expired");
+                subscription.abort();
+                beats.abort();
+                let _ = events.send(Event::SessionExpired);
+            }
+        }));
+        self.push = Some(subscription);
+        self.heartbeat = Some(beats);
+    }
+
+    fn stop(&mut self, watch_tx: &watch::Sender<Watched>) {
+        for handle in [
+            self.push.take(),
+            self.watchdog.take(),
+            self.heartbeat.take(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            handle.abort();
+        }
+        watch_tx.send_replace(None);
+    }
+}
+
+/// Times a cheap call every so often, for the latency in the status bar and
+/// to notice the server going away, or coming back, while nothing else is
+/// being sent. Each answer passes on the server's limits too, which is how
+/// they're learned after logging in.
+async fn beat(rpc: Arc<RpcClient>, events: Sender<Event<KeyEvent>>) {
+    let mut interval = tokio::time::interval(HEARTBEAT);
+    loop {
+        interval.tick().await;
+        let started = Instant::now();
+        // Any answer at all, even an error, shows the server is there
+        let result = match interact::get_server_info(&rpc).await {
+            Ok(info) => {
+                // Servers without this just don't advertise any limits
+                let _ = events.send(Event::ServerInfo(info));
+                Ok(started.elapsed())
+            }
+            Err(err @ Error::NetworkError(_)) => Err(err),
+            Err(_) => Ok(started.elapsed()),
+        };
+        if events.send(Event::Heartbeat(result)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Tells the outbox how sending the message with `client_id` went.
+fn report_sent(result: Result<(), Error>, client_id: String, events: &Sender<Event<KeyEvent>>) {
+    let _ = events.send(match result {
+        Ok(()) => Event::Sent(client_id),
+        Err(error) => Event::SendFailed { client_id, error },
+    });
+}
+
+/// Reports a failed room action, or shows its effect by re-fetching the rooms.
+async fn after_room_action(
+    result: Result<(), Error>,
+    rpc: &RpcClient,
+    events: &Sender<Event<KeyEvent>>,
+) {
+    match result {
+        Ok(()) => {
+            let _ = events.send(Event::Rooms(interact::get_rooms(rpc).await));
+        }
+        Err(err) => {
+            let _ = events.send(Event::RoomActionFailed(err));
+        }
+    }
+}
+
+async fn refresh_friends(rpc: &RpcClient, events: &Sender<Event<KeyEvent>>) {
+    let friends = interact::get_friends(rpc).await;
+    let _ = events.send(Event::Friends(friends));
+    let requests = interact::get_friend_requests(rpc).await;
+    let _ = events.send(Event::FriendRequests(requests));
+}
+
+/// Reports a failed friend action, or shows its effect by re-fetching the lists.
+async fn after_friend_action(
+    result: Result<(), Error>,
+    rpc: &RpcClient,
+    events: &Sender<Event<KeyEvent>>,
+) {
+    match result {
+        Ok(()) => refresh_friends(rpc, events).await,
+        Err(err) => {
+            let _ = events.send(Event::FriendActionFailed(err));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        env,
+        net::{TcpListener, TcpStream},
+        process::{self, Child, Stdio},
+        thread,
+    };
+    use tempfile::TempDir;
+
+    /// How long anything is given to happen before a test gives up on it.
+    const PATIENCE: Duration = Duration::from_secs(10);
+
+    /// `examples/mock_server` on a port of its own, stopped when dropped.
+    struct MockServer {
+        process: Child,
+        /// Points saved sessions and caches at `_data`, not the user's.
+        config: ServerConfig,
+        _data: TempDir,
+    }
+
+    impl MockServer {
+        fn start(args: &[&str]) -> MockServer {
+            let port = TcpListener::bind("127.0.0.1:0")
+                .and_then(|listener| listener.local_addr())
+                .expect("no free port")
+                .port();
+            // cargo test builds the examples next to the test binary's directory
+            let exe = env::current_exe().unwrap();
+            let path = exe
+                .parent()
+                .and_then(|deps| deps.parent())
+                .unwrap()
+                .join("examples")
+                .join(format!("mock_server{}", env::consts::EXE_SUFFIX));
+            let process = process::Command::new(&path)
+                .args(["--port", &port.to_string()])
+                .args(args)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .unwrap_or_else(|err| panic!("couldn't start {}: {err}", path.display()));
+
+            let started = Instant::now();
+            while TcpStream::connect(("127.0.0.1", port)).is_err() {
+                assert!(
+                    started.elapsed() < PATIENCE,
+                    "the mock server never came up"
+                );
+                thread::sleep(Duration::from_millis(50));
+            }
+            let data = TempDir::new().unwrap();
+            MockServer {
+                process,
+                config: ServerConfig {
+                    base_url: format!("http://127.0.0.1:{port}"),
+                    data_dir: Some(data.path().to_path_buf()),
+                    ..ServerConfig::default()
+                },
+                _data: data,
+            }
+        }
+
+        /// Takes the server away, leaving what was saved for it.
+        fn stop(&mut self) {
+            let _ = self.process.kill();
+            let _ = self.process.wait();
+        }
+    }
+
+    impl Drop for MockServer {
+        fn drop(&mut self) {
+            self.stop();
+        }
+    }
+
+    /// A network task of its own, as one run of the app would have.
+    struct Client {
+        commands: mpsc::UnboundedSender<Command>,
+        events: std::sync::mpsc::Receiver<Event<KeyEvent>>,
+    }
+
+    impl Client {
+        fn new(runtime: &Runtime) -> Client {
+            let (tx, events) = std::sync::mpsc::channel();
+            Client {
+                commands: spawn(runtime, tx),
+                events,
+            }
+        }
+
+        fn send(&self, command: Command) {
+            self.commands.send(command).unwrap();
+        }
+
+        /// The first event `wanted` picks out, skipping any others.
+        fn expect<T>(&self, mut wanted: impl FnMut(Event<KeyEvent>) -> Option<T>) -> T {
+            let deadline = Instant::now() + PATIENCE;
+            loop {
+                let left = deadline.saturating_duration_since(Instant::now());
+                let event = self.events.recv_timeout(left).expect("timed out");
+                if let Some(found) = wanted(event) {
+                    return found;
+                }
+            }
+        }
+
+        fn log_in(&self, server: &MockServer, username: &str, credentials: Credentials) {
+            self.send(Command::Login {
+                server: server.config.clone(),
+                username: username.to_string(),
+                credentials,
+            });
+            let logged_in = self.expect(|event| match event {
+                Event::LoggedIn { username, offline } => Some((username, offline)),
+                Event::LoginFailed(err) => panic!("login failed: {err}"),
+                _ => None,
+            });
+            assert_eq!(logged_in, (username.to_string(), None));
+        }
+
+        fn register(&self, server: &MockServer, username: &str) {
+            let credentials = Credentials::Password {
+                pwd: "secretpw".to_string(),
+                register: true,
+            };
+            self.log_in(server, username, credentials);
+        }
+    }
+
+    fn saved_session(server: &MockServer, username: &str) -> SavedSession {
+        session::accounts(&server.config)
+            .into_iter()
+            .find(|saved| saved.username == username)
+            .expect("the session wasn't saved")
+    }
+
+    #[test]
+    fn registers_and_logs_in() {
+        let runtime = Runtime::new().unwrap();
+        let server = MockServer::start(&[]);
+        let client = Client::new(&runtime);
+
+        client.register(&server, "alice");
+        client.send(Command::GetRooms);
+        let rooms = client.expect(|event| match event {
+            Event::Rooms(rooms) => Some(rooms.unwrap()),
+            _ => None,
+        });
+        assert_eq!(rooms.len(), 2);
+        assert!(rooms.iter().all(|room| room.joined));
+    }
+
+    #[test]
+    fn wrong_password_is_refused() {
+        let runtime = Runtime::new().unwrap();
+        let server = MockServer::start(&[]);
+        Client::new(&runtime).register(&server, "alice");
+
+        let client = Client::new(&runtime);
+        client.send(Command::Login {
+            server: server.config.clone(),
+            username: "alice".to_string(),
+            credentials: Credentials::Password {
+                pwd: "not it".to_string(),
+                register: false,
+            },
+        });
+        client.expect(|event| match event {
+            Event::LoginFailed(_) => Some(()),
+            Event::LoggedIn { .. } => panic!("logged in with the wrong password"),
+            _ => None,
+        });
+    }
+
+    #[test]
+    fn restores_a_saved_session() {
+        let runtime = Runtime::new().unwrap();
+        let server = MockServer::start(&[]);
+        Client::new(&runtime).register(&server, "alice");
+
+        // The next run starts with an empty cookie jar
+        let saved = saved_session(&server, "alice");
+        assert!(saved.resumable());
+        let client = Client::new(&runtime);
+        client.log_in(&server, "alice", Credentials::Saved(saved));
+        // The restored cookie has to get the WebSocket in too
+        client.expect(|event| match event {
+            Event::PushConnected => Some(()),
+            Event::PushLost(err) => panic!("push refused a restored session: {err}"),
+            _ => None,
+        });
+        client.send(Command::GetFriends);
+        client.expect(|event| match event {
+            Event::Friends(friends) => Some(friends.unwrap()),
+            _ => None,
+        });
+    }
+
+    #[test]
+    fn expired_saved_session_is_forgotten() {
+        let runtime = Runtime::new().unwrap();
+        let server = MockServer::start(&["--session-ttl", "1"]);
+        Client::new(&runtime).register(&server, "alice");
+        let saved = saved_session(&server, "alice");
+        thread::sleep(Duration::from_millis(1500));
+
+        let client = Client::new(&runtime);
+        client.send(Command::Login {
+            server: server.config.clone(),
+            username: "alice".to_string(),
+            credentials: Credentials::Saved(saved),
+        });
+        client.expect(|event| match event {
+            Event::LoginFailed(_) => Some(()),
+            Event::LoggedIn { .. } => panic!("an expired session was let back in"),
+            _ => None,
+        });
+        assert!(!saved_session(&server, "alice").resumable());
+    }
+
+    #[test]
+    fn only_a_saved_session_opens_the_cache_offline() {
+        let runtime = Runtime::new().unwrap();
+        let mut server = MockServer::start(&[]);
+        Client::new(&runtime).register(&server, "alice");
+        MessageCache::open(account_dir(&server.config, "alice")).set_rooms(&[]);
+        let saved = saved_session(&server, "alice");
+        let config = server.config.clone();
+        server.stop();
+
+        let client = Client::new(&runtime);
+        client.send(Command::Login {
+            server: config.clone(),
+            username: "alice".to_string(),
+            credentials: Credentials::Password {
+                pwd: "anything".to_string(),
+                register: false,
+            },
+        });
+        client.expect(|event| match event {
+            Event::LoginFailed(_) => Some(()),
+            Event::LoggedIn { .. } => panic!("let in offline on an unchecked password"),
+            _ => None,
+        });
+
+        client.send(Command::Login {
+            server: config,
+            username: "alice".to_string(),
+            credentials: Credentials::Saved(saved),
+        });
+        let offline = client.expect(|event| match event {
+            Event::LoggedIn { offline, .. } => Some(offline),
+            Event::LoginFailed(err) => panic!("the saved session was turned away: {err}"),
+            _ => None,
+        });
+        assert!(offline.is_some());
+    }
+
+    #[test]
+    fn polls_for_messages_without_a_websocket() {
+        let runtime = Runtime::new().unwrap();
+        let server = MockServer::start(&["--no-ws"]);
+        let alice = Client::new(&runtime);
+        alice.register(&server, "alice");
+        alice.expect(|event| match event {
+            Event::PushLost(_) => Some(()),
+            Event::PushConnected => panic!("connected to a server without a WebSocket"),
+            _ => None,
+        });
+        alice.send(Command::Watch {
+            conversation: Conversation::Room(1),
+            since_id: 0,
+        });
+
+        let bob = Client::new(&runtime);
+        bob.register(&server, "bob");
+        bob.send(Command::SendMessage {
+            room_id: 1,
+            client_id: "c1".to_string(),
+            text: "hello over the long poll".to_string(),
+        });
+        bob.expect(|event| match event {
+            Event::Sent(client_id) => Some(client_id),
+            Event::SendFailed { error, .. } => panic!("couldn't send: {error}"),
+            _ => None,
+        });
+
+        let message = alice.expect(|event| match event {
+            Event::NewMessage(message) if message.message_text.contains("long poll") => {
+                Some(message)
+            }
+            _ => None,
+        });
+        assert_eq!(message.message_user_name, "bob");
+        assert_eq!(message.message_room_id, 1);
+    }
+}
@@ -0,0 +1,355 @@
+use crate::structs::{Conversation, Error};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::PathBuf,
+    process,
+    time::{Duration, Instant},
+};
+
+/// Tries before a message is marked failed and left for the user.
+const MAX_ATTEMPTS: u32 = 6;
+/// The wait after the first failure, doubled after each one since.
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+
+/// Where an outgoing message has got to.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub enum Delivery {
+    /// On its way, or waiting to be tried again.
+    Pending,
+    /// The server has it; shown until it comes back in the conversation.
+    Sent,
+    /// Gave up, with why; waits for the user to retry or discard it.
+    Failed(String),
+}
+
+/// A message the user has sent that the conversation hasn't shown yet.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Outgoing {
+    /// Lets the server ignore a retry of something it already has.
+    pub client_id: String,
+    pub conversation: Conversation,
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+    pub delivery: Delivery,
+    attempts: u32,
+    /// When a pending message is next due to be tried; `None` while a try
+    /// is in flight.
+    #[serde(skip, default = "due_now")]
+    retry_at: Option<Instant>,
+}
+
+/// Messages read back from disk are tried again right away.
+fn due_now() -> Option<Instant> {
+    Some(Instant::now())
+}
+
+/// Messages on their way to the server, saved so that quitting or losing
+/// the connection doesn't lose them.
+#[derive(Default)]
+pub struct Outbox {
+    messages: Vec<Outgoing>,
+    path: Option<PathBuf>,
+    /// Changed since it was last saved.
+    dirty: bool,
+    /// Keeps ids made in the same instant apart.
+    sequence: u64,
+}
+
+impl Outbox {
+    /// Reads the outbox saved at `path`; anything still pending is tried
+    /// again straight away.
+    pub fn load(path: PathBuf) -> Result<Outbox, Error> {
+        let messages = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            Vec::new()
+        };
+        Ok(Outbox {
+            messages,
+            path: Some(path),
+            ..Outbox::default()
+        })
+    }
+
+    /// Saves to somewhere else from now on, as after a rename.
+    pub fn set_path(&mut self, path: PathBuf) {
+        self.path = Some(path);
+        self.dirty = true;
+    }
+
+    /// Queues `text` for `conversation` and returns it, marked as being tried.
+    pub fn push(&mut self, conversation: Conversation, text: String) -> Outgoing {
+        let created_at = Utc::now();
+        self.sequence += 1;
+        let message = Outgoing {
+            client_id: format!(
+                "{:x}-{:x}-{:x}",
+                created_at.timestamp_nanos_opt().unwrap_or_default(),
+                process::id(),
+                self.sequence
+            ),
+            conversation,
+            text,
+            created_at,
+            delivery: Delivery::Pending,
+            attempts: 1,
+            retry_at: None,
+        };
+        self.messages.push(message.clone());
+        self.dirty = true;
+        message
+    }
+
+    /// Messages whose next try is due, now marked as being tried.
+    pub fn due(&mut self) -> Vec<Outgoing> {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        for message in &mut self.messages {
+            if message.delivery == Delivery::Pending && message.retry_at.is_some_and(|at| at <= now)
+            {
+                message.retry_at = None;
+                message.attempts += 1;
+                due.push(message.clone());
+            }
+        }
+        due
+    }
+
+    pub fn sent(&mut self, client_id: &str) {
+        if let Some(message) = self.find(client_id) {
+            message.delivery = Delivery::Sent;
+            self.dirty = true;
+        }
+    }
+
+    /// Schedules another try after a growing wait, or gives up when `error`
+    /// won't go away by trying again or the tries have run out.
+    pub fn failed(&mut self, client_id: &str, error: &Error) {
+        let Some(message) = self.find(client_id) else {
+            return;
+        };
+        if let Error::SessionExpired = error {
+            // Sent again once logged back in, without counting as a try
+            message.attempts = message.attempts.saturating_sub(1);
+            message.retry_at = Some(Instant::now());
+            self.dirty = true;
+            return;
+        }
+        let transient = match error {
+            Error::NetworkError(_) => true,
+            Error::HttpStatusError(status) => status.is_server_error(),
+            _ => false,
+        };
+        if transient && message.attempts < MAX_ATTEMPTS {
+            message.retry_at = Some(Instant::now() + FIRST_RETRY * 2u32.pow(message.attempts - 1));
+        } else {
+            message.delivery = Delivery::Failed(error.to_string());
+        }
+        self.dirty = true;
+    }
+
+    /// Sends the failed messages in `conversation` again.
+    pub fn retry(&mut self, conversation: &Conversation) {
+        for message in self.failed_in(conversation) {
+            message.delivery = Delivery::Pending;
+            message.attempts = 0;
+            message.retry_at = Some(Instant::now());
+        }
+        self.dirty = true;
+    }
+
+    /// Drops the failed messages in `conversation`. Returns how many.
+    pub fn discard(&mut self, conversation: &Conversation) -> usize {
+        let before = self.messages.len();
+        self.messages.retain(|message| {
+            &message.conversation != conversation
+                || !matches!(message.delivery, Delivery::Failed(_))
+        });
+        self.dirty = true;
+        before - self.messages.len()
+    }
+
+    /// Forgets sent messages that have now shown up in the conversation,
+    /// recognised by their client id or, from servers that don't echo one,
+    /// by their text.
+    pub fn arrived<'m>(
+        &mut self,
+        conversation: &Conversation,
+        messages: impl IntoIterator<Item = (Option<&'m str>, &'m str)>,
+    ) {
+        for (client_id, text) in messages {
+            let position = self.messages.iter().position(|message| {
+                &message.conversation == conversation
+                    && match client_id {
+                        Some(id) => message.client_id == id,
+                        None => message.delivery == Delivery::Sent && message.text == text,
+                    }
+            });
+            if let Some(position) = position {
+                self.messages.remove(position);
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// What's still on its way in `conversation`, oldest first.
+    pub fn in_conversation<'a>(
+        &'a self,
+        conversation: &'a Conversation,
+    ) -> impl Iterator<Item = &'a Outgoing> {
+        self.messages
+            .iter()
+            .filter(move |message| &message.conversation == conversation)
+    }
+
+    /// Writes out any changes since the last save. Sent messages aren't
+    /// kept, since the server has them.
+    pub fn save(&mut self) -> Result<(), Error> {
+        let Some(path) = self.path.as_ref().filter(|_| self.dirty) else {
+            return Ok(());
+        };
+        self.dirty = false;
+        let unsent: Vec<&Outgoing> = self
+            .messages
+            .iter()
+            .filter(|message| message.delivery != Delivery::Sent)
+            .collect();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(&unsent)?)?;
+        Ok(())
+    }
+
+    fn find(&mut self, client_id: &str) -> Option<&mut Outgoing> {
+        self.messages
+            .iter_mut()
+            .find(|message| message.client_id == client_id)
+    }
+
+    fn failed_in<'a>(
+        &'a mut self,
+        conversation: &'a Conversation,
+    ) -> impl Iterator<Item = &'a mut Outgoing> {
+        self.messages.iter_mut().filter(move |message| {
+            &message.conversation == conversation && matches!(message.delivery, Delivery::Failed(_))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    fn unavailable() -> Error {
+        Error::HttpStatusError(StatusCode::SERVICE_UNAVAILABLE)
+    }
+
+    /// Fails the only message with `error`, returning how long it now waits.
+    fn fail(outbox: &mut Outbox, error: &Error) -> Option<Duration> {
+        let client_id = outbox.messages[0].client_id.clone();
+        let before = Instant::now();
+        outbox.failed(&client_id, error);
+        outbox.messages[0]
+            .retry_at
+            .map(|at| at.saturating_duration_since(before))
+    }
+
+    /// Whether `wait` is `expected` give or take the time the call took.
+    fn about(wait: Option<Duration>, expected: Duration) -> bool {
+        wait.is_some_and(|wait| wait >= expected && wait - expected < Duration::from_millis(100))
+    }
+
+    /// Skips the wait, as if it had passed, and takes the message to try again.
+    fn try_again(outbox: &mut Outbox) {
+        outbox.messages[0].retry_at = Some(Instant::now());
+        assert_eq!(outbox.due().len(), 1);
+    }
+
+    fn outbox_with_one() -> Outbox {
+        let mut outbox = Outbox::default();
+        outbox.push(Conversation::Room(1), "hello".to_string());
+        outbox
+    }
+
+    #[test]
+    fn waits_twice_as_long_after_each_failure() {
+        let mut outbox = outbox_with_one();
+        for expected in [1, 2, 4, 8, 16] {
+            let wait = fail(&mut outbox, &unavailable());
+            assert!(about(wait, Duration::from_secs(expected)), "{wait:?}");
+            assert!(
+                outbox.due().is_empty(),
+                "tried again before the wait was up"
+            );
+            try_again(&mut outbox);
+        }
+    }
+
+    #[test]
+    fn gives_up_after_the_last_attempt() {
+        let mut outbox = outbox_with_one();
+        for _ in 1..MAX_ATTEMPTS {
+            assert!(fail(&mut outbox, &unavailable()).is_some());
+            try_again(&mut outbox);
+        }
+        assert_eq!(outbox.messages[0].attempts, MAX_ATTEMPTS);
+        fail(&mut outbox, &unavailable());
+        assert!(matches!(outbox.messages[0].delivery, Delivery::Failed(_)));
+        assert!(outbox.due().is_empty());
+    }
+
+    #[test]
+    fn retrying_by_hand_starts_the_count_again() {
+        let mut outbox = outbox_with_one();
+        for _ in 1..MAX_ATTEMPTS {
+            fail(&mut outbox, &unavailable());
+            try_again(&mut outbox);
+        }
+        fail(&mut outbox, &unavailable());
+        outbox.retry(&Conversation::Room(1));
+        assert_eq!(outbox.due().len(), 1);
+        assert!(about(fail(&mut outbox, &unavailable()), FIRST_RETRY));
+    }
+
+    #[test]
+    fn errors_that_would_only_happen_again_fail_at_once() {
+        for error in [
+            Error::HttpStatusError(StatusCode::BAD_REQUEST),
+            Error::RpcError {
+                code: -32000,
+                message: "too long".to_string(),
+            },
+        ] {
+            let mut outbox = outbox_with_one();
+            fail(&mut outbox, &error);
+            assert!(matches!(outbox.messages[0].delivery, Delivery::Failed(_)));
+        }
+    }
+
+    #[test]
+    fn an_expired_session_costs_no_attempt() {
+        let mut outbox = outbox_with_one();
+        for _ in 0..MAX_ATTEMPTS * 2 {
+            assert!(about(
+                fail(&mut outbox, &Error::SessionExpired),
+                Duration::ZERO
+            ));
+            try_again(&mut outbox);
+        }
+        assert_eq!(outbox.messages[0].attempts, 1);
+        assert!(outbox.messages[0].delivery == Delivery::Pending);
+    }
+
+    #[test]
+    fn an_expired_session_before_any_attempt_is_still_saved() {
+        let mut outbox = outbox_with_one();
+        outbox.dirty = false;
+        fail(&mut outbox, &Error::SessionExpired);
+        assert_eq!(outbox.messages[0].attempts, 0);
+        assert!(outbox.dirty);
+    }
+}
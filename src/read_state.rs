@@ -0,0 +1,111 @@
+use crate::structs::{Conversation, Error};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::PathBuf,
+};
+
+/// How far the user has read in each conversation, saved between runs, and
+/// which messages have turned up since.
+#[derive(Default, Serialize, Deserialize)]
+pub struct ReadState {
+    /// Newest message id read, by room id.
+    rooms: HashMap<i64, i64>,
+    /// Newest private message id read, by friend.
+    friends: HashMap<String, i64>,
+    /// Ids of other people's messages newer than what's been read.
+    #[serde(skip)]
+    unread: HashMap<Conversation, BTreeSet<i64>>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// Changed since it was last saved.
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl ReadState {
+    /// Reads the state saved at `path`, starting afresh if there is none.
+    pub fn load(path: PathBuf) -> Result<ReadState, Error> {
+        let mut state = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            ReadState::default()
+        };
+        state.path = Some(path);
+        Ok(state)
+    }
+
+    /// Saves to somewhere else from now on, as after a rename.
+    pub fn set_path(&mut self, path: PathBuf) {
+        self.path = Some(path);
+        self.dirty = true;
+    }
+
+    pub fn last_read(&self, conversation: &Conversation) -> Option<i64> {
+        match conversation {
+            Conversation::Room(id) => self.rooms.get(id).copied(),
+            Conversation::Direct(friend) => self.friends.get(friend).copied(),
+        }
+    }
+
+    /// Notes messages loaded or received in `conversation`, as `(id, ours)`
+    /// pairs. A conversation never opened before starts out read rather than
+    /// with its whole history unread.
+    pub fn seen(
+        &mut self,
+        conversation: &Conversation,
+        messages: impl IntoIterator<Item = (i64, bool)>,
+    ) {
+        let messages: Vec<(i64, bool)> = messages.into_iter().collect();
+        let Some(last_read) = self.last_read(conversation) else {
+            let newest = messages.iter().map(|(id, _)| *id).max();
+            self.mark_read(conversation, newest.unwrap_or(0));
+            return;
+        };
+
+        let unread = self.unread.entry(conversation.clone()).or_default();
+        unread.extend(
+            messages
+                .into_iter()
+                .filter(|(id, ours)| *id > last_read && !ours)
+                .map(|(id, _)| id),
+        );
+    }
+
+    /// Records everything up to `newest` as read.
+    pub fn mark_read(&mut self, conversation: &Conversation, newest: i64) {
+        if let Some(unread) = self.unread.get_mut(conversation) {
+            unread.retain(|id| *id > newest);
+        }
+        if self
+            .last_read(conversation)
+            .is_some_and(|read| read >= newest)
+        {
+            return;
+        }
+        match conversation {
+            Conversation::Room(id) => self.rooms.insert(*id, newest),
+            Conversation::Direct(friend) => self.friends.insert(friend.clone(), newest),
+        };
+        self.dirty = true;
+    }
+
+    pub fn unread(&self, conversation: &Conversation) -> usize {
+        self.unread.get(conversation).map_or(0, BTreeSet::len)
+    }
+
+    /// Writes out any changes since the last save.
+    pub fn save(&mut self) -> Result<(), Error> {
+        let Some(path) = self.path.as_ref().filter(|_| self.dirty) else {
+            return Ok(());
+        };
+        // Not retried until the next change, so a failure isn't reported every frame
+        self.dirty = false;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
@@ -1,65 +1,120 @@
-use crate::interact;
-use crate::Message;
+use crate::interact::{Message, Room};
+use crate::outbox::Outgoing;
+use crate::read_state::ReadState;
+use crate::render::timeline::{timeline, Entry, TimelineStyle};
+use crate::structs::{Conversation, Scrollback};
 use ratatui::{
     prelude::*,
     style::{Color, Style},
     text::Span,
     widgets::*,
 };
-use reqwest::Client;
+
+/// What the Rooms pane lists.
+pub struct RoomsPane<'b> {
+    pub rooms: &'b [Room],
+    pub selected: Option<i64>,
+    pub error: Option<&'b str>,
+    pub read: &'b ReadState,
+}
 
 pub fn render_chat_rooms<'a>(
-    chat_rooms: &Vec<interact::Room>,
-    client: &'a Client,
-    room_list_state: &'a ListState,
-) -> (List<'a>, List<'a>) {
+    pane: &RoomsPane,
+    chat: &[Message],
+    unsent: &[&Outgoing],
+    chat_error: Option<&str>,
+    scroll: &Scrollback,
+    style: &TimelineStyle,
+) -> (List<'a>, ListState, List<'a>) {
+    let selected_room = pane.selected;
     let room_block = Block::default()
         .borders(Borders::ALL)
         .style(Style::default().fg(Color::White))
         .title("Rooms")
         .border_type(BorderType::Plain);
 
+    let topic = pane
+        .rooms
+        .iter()
+        .find(|room| Some(room.id) == selected_room)
+        .and_then(|room| room.topic.as_deref())
+        .filter(|topic| !topic.is_empty());
+    let mut chat_title = match topic {
+        Some(topic) => format!("Chat: {topic}"),
+        None => "Chat".to_string(),
+    };
+    if scroll.offset > 0 {
+        chat_title.push_str(" (scrolled back, End for latest)");
+    }
+
     let chat_block = Block::default()
         .borders(Borders::ALL)
         .style(Style::default().fg(Color::White))
-        .title("Chat")
+        .title(chat_title)
         .border_type(BorderType::Plain);
 
     // creates list of rooms
-    let room_items: Vec<_> = chat_rooms
+    let mut room_items: Vec<_> = pane
+        .rooms
         .iter()
-        .enumerate()
-        .map(|(index, room)| {
-            ListItem::new(Span::from(format!("{}:{}", room.id, room.title))).style(
-                if room_list_state.selected() == Some(index) {
-                    Style::default().add_modifier(Modifier::REVERSED)
-                } else {
-                    Style::default()
-                },
-            )
+        .map(|room| {
+            // Rooms we aren't in are listed dimmed, so they can still be browsed and joined
+            let mut style = if room.joined {
+                Style::default()
+            } else {
+                Style::default().fg(Color::DarkGray)
+            };
+            let mut label = format!("{}:{}", room.id, room.title);
+            let unread = pane.read.unread(&Conversation::Room(room.id));
+            if unread > 0 {
+                label.push_str(&format!(" ({unread})"));
+                style = style.add_modifier(Modifier::BOLD);
+            }
+            ListItem::new(Span::from(label)).style(if selected_room == Some(room.id) {
+                style.add_modifier(Modifier::REVERSED)
+            } else {
+                style
+            })
         })
         .collect();
 
-    // Get the selected chat room name
-    let chat = if let Some(selected) = room_list_state.selected() {
-        let room_id = selected as i64; // Cast usize to i64
-        tokio::runtime::Runtime::new()
-            .unwrap()
-            .block_on(async { interact::get_messages(&client, room_id).await })
-    } else {
-        Vec::<Message>::new() // Handle error here
-    };
+    if let Some(error) = pane.error {
+        room_items.push(error_item(error));
+    }
 
-    // creates chat history
-    let chat_items: Vec<_> = chat
-        .iter()
-        .map(|chat| {
-            ListItem::new(Span::from(format!(
-                "{}: {}",
-                chat.message_user_name, chat.message_text
-            )))
-        })
-        .collect();
+    // creates chat history, headed by where it stands with older pages
+    let hint = Style::default().fg(Color::DarkGray);
+    let mut chat_items = vec![ListItem::new(Span::styled(
+        if scroll.loading {
+            "Loading older messages..."
+        } else if scroll.reached_start {
+            "Start of the conversation"
+        } else {
+            "PgUp for older messages"
+        },
+        hint,
+    ))];
+    let entries = chat.iter().map(Entry::from).chain(
+        unsent
+            .iter()
+            .map(|message| Entry::outgoing(message, style.username)),
+    );
+    let (messages, rows) = timeline(entries, style);
+    chat_items.extend(messages);
+    if let Some(error) = chat_error {
+        chat_items.push(error_item(error));
+    }
+
+    // The list scrolls just far enough to show the selected item, so selecting
+    // the message `offset` up from the newest puts it on the last line. At the
+    // oldest one, select the header so the day separator above it shows too.
+    let selected = match chat.len().checked_sub(1 + scroll.offset) {
+        _ if scroll.offset == 0 => chat_items.len() - 1,
+        Some(0) | None => 0,
+        Some(index) => 1 + rows[index],
+    };
+    let mut chat_state = ListState::default();
+    chat_state.select(Some(selected));
 
     let chat_room_block = List::new(chat_items).block(chat_block);
 
@@ -70,5 +125,12 @@ pub fn render_chat_rooms<'a>(
             .add_modifier(Modifier::BOLD),
     );
 
-    (chat_room_block, chat_history_block)
+    (chat_room_block, chat_state, chat_history_block)
+}
+
+fn error_item<'a>(error: &str) -> ListItem<'a> {
+    ListItem::new(Span::styled(
+        error.to_string(),
+        Style::default().fg(Color::Red),
+    ))
 }
@@ -0,0 +1,100 @@
+use crate::completion::Completion;
+use crate::structs::Prompt;
+use ratatui::{
+    prelude::*,
+    style::{Color, Style},
+    widgets::{block::Title, *},
+};
+use tui_textarea::TextArea;
+
+/// Most lines the input box grows to before it scrolls instead.
+const MAX_LINES: usize = 8;
+/// Most matches the completion popup lists before it scrolls.
+const MAX_COMPLETIONS: usize = 6;
+
+/// Rows the input box needs for what's been typed, borders included.
+pub fn composer_height(textarea: &TextArea) -> u16 {
+    textarea.lines().len().clamp(1, MAX_LINES) as u16 + 2
+}
+
+/// The input box's frame: what it's asking for, and for messages a count of
+/// characters against the server's limit, red once over it.
+pub fn composer_block<'a>(
+    prompt: Option<Prompt>,
+    textarea: &TextArea,
+    max_length: Option<usize>,
+) -> Block<'a> {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Green))
+        .title(prompt.map_or("Input", |prompt| prompt.title()));
+
+    if prompt.is_some() {
+        return block;
+    }
+
+    let length = message_length(textarea);
+    let (counter, over) = match max_length {
+        Some(max) => (format!(" {length}/{max} "), length > max),
+        None => (format!(" {length} "), false),
+    };
+    block
+        .title(
+            Title::from(Span::styled(
+                counter,
+                Style::default().fg(if over { Color::Red } else { Color::Green }),
+            ))
+            .alignment(Alignment::Right),
+        )
+        .title(
+            Title::from(" Enter: send, Alt+Enter: new line ")
+                .position(block::Position::Bottom)
+                .alignment(Alignment::Right),
+        )
+}
+
+/// Characters in the message as it will be sent, line breaks included.
+pub fn message_length(textarea: &TextArea) -> usize {
+    let lines = textarea.lines();
+    lines.iter().map(|line| line.chars().count()).sum::<usize>() + lines.len() - 1
+}
+
+/// Where the completion popup goes: just above the input box, as wide as
+/// the longest match and clipped to the space there is.
+pub fn completion_area(input: Rect, completion: &Completion) -> Rect {
+    let longest = completion
+        .candidates
+        .iter()
+        .map(|candidate| candidate.chars().count())
+        .max()
+        .unwrap_or_default();
+    let rows = completion.candidates.len().min(MAX_COMPLETIONS) as u16 + 2;
+    let height = rows.min(input.y);
+    Rect {
+        x: input.x,
+        y: input.y - height,
+        width: (longest as u16 + 4).min(input.width),
+        height,
+    }
+}
+
+/// The matches Tab cycles through, with the one in the input box highlighted.
+pub fn render_completions<'a>(completion: &Completion) -> (List<'a>, ListState) {
+    let items: Vec<ListItem> = completion
+        .candidates
+        .iter()
+        .map(|candidate| ListItem::new(candidate.trim_end().to_string()))
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Green))
+                .title("Tab"),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    let mut state = ListState::default();
+    state.select(Some(completion.selected));
+    (list, state)
+}
@@ -0,0 +1,46 @@
+use crate::logging::LogLine;
+use ratatui::{
+    prelude::*,
+    style::{Color, Style},
+    text::Span,
+    widgets::*,
+};
+use std::collections::VecDeque;
+use tracing::Level;
+
+/// The newest log lines that fit in `height` rows, the latest at the bottom.
+pub fn render_debug_log<'a>(lines: &VecDeque<LogLine>, height: u16) -> List<'a> {
+    let shown = usize::from(height.saturating_sub(2));
+    let items: Vec<_> = lines
+        .iter()
+        .skip(lines.len().saturating_sub(shown))
+        .map(|line| {
+            let color = match line.level {
+                Level::ERROR => Color::Red,
+                Level::WARN => Color::Yellow,
+                Level::INFO => Color::White,
+                _ => Color::DarkGray,
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("{} ", line.time.format("%H:%M:%S%.3f")),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(format!("{:5} ", line.level), Style::default().fg(color)),
+                Span::styled(
+                    format!("{}: ", line.target),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(line.text.clone(), Style::default().fg(color)),
+            ]))
+        })
+        .collect();
+
+    List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .style(Style::default().fg(Color::White))
+            .title("Debug log (F12: hide)")
+            .border_type(BorderType::Plain),
+    )
+}
@@ -1,13 +1,36 @@
-use crate::structs::{Chat, Error, User};
+use crate::interact::{DirectMessage, FriendRequest};
+use crate::outbox::Outgoing;
+use crate::read_state::ReadState;
+use crate::render::timeline::{timeline, Entry, TimelineStyle};
+use crate::structs::Conversation;
 use ratatui::{
     prelude::*,
     style::{Color, Style},
     text::Span,
     widgets::*,
 };
-use std::fs;
 
-pub fn render_friends<'a>(friends_list_state: &ListState) -> (List<'a>, List<'a>, Block) {
+/// Everything the Options pane shows besides the selected friend.
+pub struct FriendOptions<'b> {
+    pub username: &'b str,
+    pub requests: &'b [FriendRequest],
+    pub selected_request: Option<i64>,
+    /// 'r' was pressed once and is waiting for a second press to remove.
+    pub confirm_remove: bool,
+    pub error: Option<&'b str>,
+    pub read: &'b ReadState,
+    /// Our messages to the selected friend still in the outbox.
+    pub unsent: &'b [&'b Outgoing],
+    pub timeline: TimelineStyle<'b>,
+}
+
+pub fn render_friends<'a>(
+    friends: &[String],
+    selected_friend: Option<&str>,
+    chat: &[DirectMessage],
+    chat_error: Option<&str>,
+    options: &FriendOptions,
+) -> (List<'a>, List<'a>, ListState, List<'a>) {
     let friend_block = Block::default()
         .borders(Borders::ALL)
         .style(Style::default().fg(Color::White))
@@ -26,76 +49,116 @@ pub fn render_friends<'a>(friends_list_state: &ListState) -> (List<'a>, List<'a>
         .title("Options")
         .border_type(BorderType::Plain);
 
-    let friends_list = read_db().unwrap();
-
-    let friends: Vec<_> = friends_list
-        .friends
+    let friend_items: Vec<_> = friends
         .iter()
-        .enumerate()
-        .map(|(index, friend)| {
-            ListItem::new(Span::from(String::from(friend.clone()))).style(
-                if friends_list_state.selected() == Some(index) {
-                    Style::default().add_modifier(Modifier::REVERSED)
-                } else {
-                    Style::default()
-                },
-            )
+        .map(|friend| {
+            let mut label = friend.clone();
+            let mut style = Style::default();
+            let unread = options.read.unread(&Conversation::Direct(friend.clone()));
+            if unread > 0 {
+                label.push_str(&format!(" ({unread})"));
+                style = style.add_modifier(Modifier::BOLD);
+            }
+            ListItem::new(Span::from(label)).style(if selected_friend == Some(friend.as_str()) {
+                style.add_modifier(Modifier::REVERSED)
+            } else {
+                style
+            })
         })
         .collect();
 
-    let list = List::new(friends).block(friend_block).highlight_style(
+    let list = List::new(friend_items).block(friend_block).highlight_style(
         Style::default()
             .bg(Color::Yellow)
             .fg(Color::Black)
             .add_modifier(Modifier::BOLD),
     );
 
-    let selected_friend = if let Some(selected_index) = friends_list_state.selected() {
-        friends_list
-            .friends
-            .get(selected_index)
-            .map(|name| name.as_str())
-    } else {
-        None
-    };
-
-    let chat_history = match selected_friend {
-        Some(friend_name) => get_private_chat(friend_name),
-        None => Ok(Vec::new()), // Handle the case when no chat is selected
-    };
-
     // creates chat history
-    let chat_items: Vec<_> = chat_history
-        .as_ref()
-        .map(|chats| {
-            chats
-                .iter()
-                .map(|chat| ListItem::new(Span::from(format!("{}: {}", chat.username, chat.text))))
-                .collect()
-        })
-        .unwrap_or_else(|_| vec![ListItem::new(Span::raw("Error reading chat data"))]);
-
-    let chat_history_block = List::new(chat_items).block(message_block).highlight_style(
-        Style::default()
-            .bg(Color::Yellow)
-            .fg(Color::Black)
-            .add_modifier(Modifier::BOLD),
+    let entries = chat.iter().map(Entry::from).chain(
+        options
+            .unsent
+            .iter()
+            .map(|message| Entry::outgoing(message, options.timeline.username)),
     );
+    let (mut chat_items, _) = timeline(entries, &options.timeline);
+    if let Some(error) = chat_error {
+        chat_items.push(ListItem::new(Span::styled(
+            error.to_string(),
+            Style::default().fg(Color::Red),
+        )));
+    }
 
-    (list, chat_history_block, options_block)
-}
+    // Selecting the last row keeps the newest messages in view
+    let mut chat_state = ListState::default();
+    chat_state.select(chat_items.len().checked_sub(1));
+    let chat_history_block = List::new(chat_items).block(message_block);
 
-const DB_PATH: &str = "./data/db.json";
+    let options_list = List::new(render_options(selected_friend, options)).block(options_block);
 
-pub fn read_db() -> Result<User, Error> {
-    let db_content = fs::read_to_string(DB_PATH)?;
-    let parsed: User = serde_json::from_str(&db_content)?;
-    Ok(parsed)
+    (list, chat_history_block, chat_state, options_list)
 }
 
-pub fn get_private_chat(selected_friend: &str) -> Result<Vec<Chat>, Error> {
-    let chat_path = format!("./data/{selected_friend}.json");
-    let chat_content = fs::read_to_string(chat_path)?;
-    let parsed: Vec<Chat> = serde_json::from_str(&chat_content)?;
-    Ok(parsed)
+fn render_options<'a>(selected_friend: Option<&str>, options: &FriendOptions) -> Vec<ListItem<'a>> {
+    let heading = Style::default().add_modifier(Modifier::BOLD);
+    let hint = Style::default().fg(Color::DarkGray);
+
+    let mut items = vec![ListItem::new(Span::from("a: Add friend"))];
+    if let Some(friend) = selected_friend {
+        items.push(if options.confirm_remove {
+            ListItem::new(Span::styled(
+                format!("r: Press again to remove {friend}"),
+                Style::default().fg(Color::Red),
+            ))
+        } else {
+            ListItem::new(Span::from(format!("r: Remove {friend}")))
+        });
+    }
+
+    items.push(ListItem::new(""));
+    items.push(ListItem::new(Span::styled("Friend requests", heading)));
+
+    let (incoming, outgoing): (Vec<_>, Vec<_>) = options
+        .requests
+        .iter()
+        .partition(|request| request.to_user_name == options.username);
+
+    if incoming.is_empty() && outgoing.is_empty() {
+        items.push(ListItem::new(Span::styled("No pending requests", hint)));
+    }
+    if !incoming.is_empty() {
+        items.push(ListItem::new(Span::styled(
+            "Tab: select, y: accept, n: decline",
+            hint,
+        )));
+    }
+    for request in incoming {
+        items.push(
+            ListItem::new(Span::from(format!(
+                "{} wants to be friends",
+                request.from_user_name
+            )))
+            .style(if options.selected_request == Some(request.id) {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            }),
+        );
+    }
+    for request in outgoing {
+        items.push(ListItem::new(Span::styled(
+            format!("Sent to {} (pending)", request.to_user_name),
+            hint,
+        )));
+    }
+
+    if let Some(error) = options.error {
+        items.push(ListItem::new(""));
+        items.push(ListItem::new(Span::styled(
+            error.to_string(),
+            Style::default().fg(Color::Red),
+        )));
+    }
+
+    items
 }
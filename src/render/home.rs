@@ -1,3 +1,6 @@
+use crate::config::Config;
+use crate::login::LoginForm;
+use crate::session::SavedSession;
 use ratatui::{
     layout::Alignment,
     prelude::*,
@@ -11,26 +14,160 @@ pub fn render_home<'a>() -> Paragraph<'a> {
         Line::from(vec![Span::from("Welcome ")]),
         Line::from(vec![Span::from("to ")]),
         Line::from(vec![Span::styled("CLI Chat ", Style::new().fg(Color::LightBlue))]),
-        Line::from(vec![Span::raw("Press 'c' to see a list of chat rooms, press 'f' to open your friends list. Press 'q' while in menus to quit.")]),
+        Line::from(vec![Span::raw("Log in or create an account to get started.")]),
+        Line::from(vec![Span::raw("Press 'c' to see a list of chat rooms, press 'f' to open your friends list. Press 'q' while in menus to quit, or Ctrl+Z to suspend.")]),
         Line::from(vec![Span::styled("Chat Rooms:", Style::new().fg(Color::LightBlue))]),
         Line::from(vec![Span::from("Use the up and down arrow keys to navigate between chat rooms.")]),
-        Line::from(vec![Span::from("Press Enter to allow for text input in a chat room, press Enter again when done typing to send message.")]),
+        Line::from(vec![Span::from("Press 'n' to create a room, 'e' to rename it, 'j' to join, 'l' to leave and 'r' to refresh the list. Rooms you haven't joined are greyed out.")]),
+        Line::from(vec![Span::from("Press 'i' to allow for text input in a chat room, press Enter when done typing to send message.")]),
+        Line::from(vec![Span::from("Alt+Enter or Shift+Enter starts a new line; the counter shows how much of the server's length limit is used.")]),
         Line::from(vec![Span::from("Press Esc to exit text entry mode at any time without sending a message.")]),
+        Line::from(vec![Span::from("Messages wait in an outbox until the server has them. If one can't be sent, press 's' to retry or 'x' to discard it.")]),
+        Line::from(vec![Span::from("Start with / for commands such as /join, /msg and /me; Tab completes them and /help lists them all.")]),
+        Line::from(vec![Span::from("Tab also completes @names, #rooms and friends after /msg; press it again for the next match.")]),
         Line::from(vec![Span::styled("Friends List:", Style::new().fg(Color::LightBlue))]),
         Line::from(vec![Span::from("Use the up and down arrow keys to navigate between friends.")]),
+        Line::from(vec![Span::styled("Accounts:", Style::new().fg(Color::LightBlue))]),
+        Line::from(vec![Span::from("On Home, pick another saved account with the arrow keys and Enter, or press 'o' to log out.")]),
+        Line::from(vec![Span::from("Each account keeps its own history, and its own settings in settings.toml in its data directory.")]),
+        Line::from(vec![Span::styled("Mentions:", Style::new().fg(Color::LightBlue))]),
+        Line::from(vec![Span::from("Press 'm' for messages that mention you or a keyword from the config. Enter jumps to one in its room.")]),
+        Line::from(vec![Span::styled("Troubleshooting:", Style::new().fg(Color::LightBlue))]),
+        Line::from(vec![Span::from("F12 shows the latest log lines. The full log, and a report of any crash, are kept under your state directory.")]),
         Line::from(vec![Span::from("For help, questions and bug reports, please contact me on github:")]),
         Line::from(vec![Span::styled("github.com/crimpz", Style::new().fg(Color::LightBlue))]),
     ];
 
-    let home = Paragraph::new(home_text)
+    Paragraph::new(home_text)
         .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true })
         .block(
             Block::default()
                 .borders(Borders::ALL)
                 .style(Style::default().fg(Color::White))
                 .title("Home")
                 .border_type(BorderType::Plain),
-        );
+        )
+}
+
+/// The accounts saved for the current server, to switch between.
+pub struct AccountList<'b> {
+    pub accounts: &'b [SavedSession],
+    pub selected: Option<&'b str>,
+}
+
+pub fn render_login_status<'a>(
+    login_form: &LoginForm,
+    config: &Config,
+    username: Option<&str>,
+    account_list: &AccountList,
+) -> Paragraph<'a> {
+    let title = match (username, login_form.register) {
+        (Some(_), _) => "Account",
+        (None, false) => "Log In",
+        (None, true) => "Create Account",
+    };
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .style(Style::default().fg(Color::White))
+        .title(title)
+        .border_type(BorderType::Plain);
 
-    home
+    let server_line = Line::from(vec![
+        Span::from("Server: "),
+        Span::styled(
+            format!("{} ({})", config.profile, config.server().base_url),
+            Style::new().fg(Color::LightBlue),
+        ),
+    ]);
+
+    if let Some(username) = username {
+        let mut lines = vec![
+            Line::from(vec![
+                Span::from("Logged in as "),
+                Span::styled(username.to_string(), Style::new().fg(Color::LightBlue)),
+            ]),
+            server_line,
+            Line::from(vec![Span::from(
+                "Press 'c' for chat rooms or 'f' for your friends list.",
+            )]),
+            Line::from(""),
+            Line::from(vec![Span::styled(
+                "Accounts",
+                Style::new().fg(Color::LightBlue),
+            )]),
+        ];
+        for saved in account_list.accounts {
+            let mut label = saved.username.clone();
+            let mut style = Style::default();
+            if saved.username == username {
+                label.push_str(" (current)");
+            } else if !saved.resumable() {
+                label.push_str(" (needs password)");
+                style = style.fg(Color::DarkGray);
+            }
+            if Some(saved.username.as_str()) == account_list.selected {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            lines.push(Line::from(vec![Span::styled(label, style)]));
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::from(vec![Span::from(
+            "Up/Down and Enter to switch account, 'o' to log out.",
+        )]));
+        return Paragraph::new(lines)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true })
+            .block(block);
+    }
+
+    let toggle_hint = if login_form.register {
+        "Ctrl+N: back to log in"
+    } else {
+        "Ctrl+N: create a new account"
+    };
+
+    // The username and password fields are drawn over the top of this block,
+    // so the hints start below them.
+    let mut lines = vec![Line::from(""); 7];
+    lines.push(Line::from(vec![Span::from(
+        "Tab to switch fields, Enter to submit.",
+    )]));
+    lines.push(Line::from(vec![Span::from(toggle_hint)]));
+    if config.servers.len() > 1 {
+        lines.push(Line::from(vec![Span::from("Ctrl+S: switch server")]));
+    }
+    if !account_list.accounts.is_empty() {
+        let names: Vec<&str> = account_list
+            .accounts
+            .iter()
+            .map(|saved| saved.username.as_str())
+            .collect();
+        lines.push(Line::from(vec![Span::from(format!(
+            "Ctrl+A: saved accounts ({})",
+            names.join(", ")
+        ))]));
+    }
+    lines.push(Line::from(vec![Span::from("Ctrl+C to quit.")]));
+    lines.push(Line::from(""));
+    lines.push(server_line);
+    if login_form.pending {
+        lines.push(Line::from(""));
+        lines.push(Line::from(vec![Span::styled(
+            "Logging in...",
+            Style::new().fg(Color::Yellow),
+        )]));
+    } else if let Some(error) = &login_form.error {
+        lines.push(Line::from(""));
+        lines.push(Line::from(vec![Span::styled(
+            error.clone(),
+            Style::new().fg(Color::Red),
+        )]));
+    }
+
+    Paragraph::new(lines)
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true })
+        .block(block)
 }
@@ -0,0 +1,625 @@
+use ratatui::{
+    prelude::*,
+    style::{Color, Modifier, Style},
+    text::Span,
+};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Words picked out in fenced code that names its language.
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "case", "class", "const", "continue", "def", "else", "elif",
+    "enum", "except", "false", "False", "fn", "for", "from", "func", "function", "if", "impl",
+    "import", "in", "let", "loop", "match", "mod", "mut", "new", "nil", "None", "null", "pub",
+    "return", "self", "static", "struct", "switch", "trait", "true", "True", "try", "type", "use",
+    "var", "while", "with", "yield",
+];
+
+/// A stretch of message text in one style.
+struct Run {
+    text: String,
+    style: Style,
+}
+
+/// One line's worth of message structure.
+enum Block {
+    Text(Vec<Run>),
+    Quote(Vec<Run>),
+    Item {
+        marker: String,
+        runs: Vec<Run>,
+    },
+    Code {
+        language: Option<String>,
+        lines: Vec<String>,
+    },
+}
+
+/// Renders a message's `**bold**`, `*italic*`, `` `code` ``, fenced code
+/// blocks, `>` quotes and `-`/`1.` lists, wrapped so the first line fits
+/// `first_width` columns and the rest `rest_width`. `base` is the style of
+/// plain text. Fenced code naming a language is highlighted if `highlight`.
+pub fn format_message<'a>(
+    text: &str,
+    base: Style,
+    first_width: usize,
+    rest_width: usize,
+    highlight: bool,
+) -> Vec<Line<'a>> {
+    let border = Style::default().fg(Color::DarkGray);
+    let mut lines: Vec<Line> = Vec::new();
+
+    for block in parse_blocks(text, base) {
+        // Only the first line shares its row with the author's name
+        let width = if lines.is_empty() {
+            first_width
+        } else {
+            rest_width
+        };
+
+        match block {
+            Block::Text(runs) => {
+                lines.extend(wrap(&runs, width, rest_width).into_iter().map(Line::from));
+            }
+            Block::Quote(runs) => {
+                let bar = "▎ ";
+                let inner = |width: usize| width.saturating_sub(2).max(1);
+                for spans in wrap(&runs, inner(width), inner(rest_width)) {
+                    let mut line = vec![Span::styled(bar, border)];
+                    line.extend(spans);
+                    lines.push(Line::from(line));
+                }
+            }
+            Block::Item { marker, runs } => {
+                let indent = marker.chars().count();
+                let inner = |width: usize| width.saturating_sub(indent).max(1);
+                for (index, spans) in wrap(&runs, inner(width), inner(rest_width))
+                    .into_iter()
+                    .enumerate()
+                {
+                    let mut line = vec![Span::styled(
+                        if index == 0 {
+                            marker.clone()
+                        } else {
+                            " ".repeat(indent)
+                        },
+                        base,
+                    )];
+                    line.extend(spans);
+                    lines.push(Line::from(line));
+                }
+            }
+            Block::Code {
+                language,
+                lines: code,
+            } => {
+                // A box can't share the name's row, so it always starts below it
+                if lines.is_empty() {
+                    lines.push(Line::from(""));
+                }
+                lines.extend(code_box(
+                    language.as_deref(),
+                    &code,
+                    rest_width,
+                    highlight,
+                    border,
+                ));
+            }
+        }
+    }
+
+    if lines.is_empty() {
+        lines.push(Line::from(""));
+    }
+    lines
+}
+
+fn parse_blocks(text: &str, base: Style) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+        if let Some(language) = trimmed.strip_prefix("```") {
+            let language = Some(language.trim())
+                .filter(|language| !language.is_empty())
+                .map(String::from);
+            // An unclosed fence runs to the end of the message
+            let code = lines
+                .by_ref()
+                .take_while(|line| !line.trim_start().starts_with("```"))
+                .map(String::from)
+                .collect();
+            blocks.push(Block::Code {
+                language,
+                lines: code,
+            });
+        } else if let Some(quoted) = trimmed.strip_prefix('>') {
+            let quoted = quoted.strip_prefix(' ').unwrap_or(quoted);
+            blocks.push(Block::Quote(parse_inline(
+                quoted,
+                base.add_modifier(Modifier::ITALIC),
+            )));
+        } else if let Some((marker, item)) = list_marker(trimmed) {
+            blocks.push(Block::Item {
+                marker,
+                runs: parse_inline(item, base),
+            });
+        } else {
+            blocks.push(Block::Text(parse_inline(line, base)));
+        }
+    }
+
+    blocks
+}
+
+/// Splits a list item into the marker to show and its text.
+fn list_marker(line: &str) -> Option<(String, &str)> {
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(item) = line.strip_prefix(bullet) {
+            return Some(("• ".to_string(), item));
+        }
+    }
+
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let rest = &line[digits..];
+    match rest.strip_prefix(". ").or_else(|| rest.strip_prefix(") ")) {
+        Some(item) if digits > 0 => Some((format!("{}. ", &line[..digits]), item)),
+        _ => None,
+    }
+}
+
+fn parse_inline(text: &str, base: Style) -> Vec<Run> {
+    let code = base.fg(Color::LightCyan);
+    let mut runs = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+
+    while let Some(ch) = rest.chars().next() {
+        let styled = if ch == '`' {
+            rest[1..].find('`').map(|end| {
+                let run = Run {
+                    text: rest[1..1 + end].to_string(),
+                    style: code,
+                };
+                (vec![run], 2 + end)
+            })
+        } else if let Some(inner) = rest.strip_prefix("**") {
+            closing(inner, "**").map(|end| {
+                let runs = parse_inline(&inner[..end], base.add_modifier(Modifier::BOLD));
+                (runs, 4 + end)
+            })
+        } else if ch == '*' {
+            closing(&rest[1..], "*").map(|end| {
+                let runs = parse_inline(&rest[1..1 + end], base.add_modifier(Modifier::ITALIC));
+                (runs, 2 + end)
+            })
+        } else {
+            None
+        };
+
+        match styled {
+            Some((styled, consumed)) => {
+                if !plain.is_empty() {
+                    runs.push(Run {
+                        text: std::mem::take(&mut plain),
+                        style: base,
+                    });
+                }
+                runs.extend(styled);
+                rest = &rest[consumed..];
+            }
+            // Unmatched markers are just text
+            None => {
+                plain.push(ch);
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+    }
+
+    if !plain.is_empty() {
+        runs.push(Run {
+            text: plain,
+            style: base,
+        });
+    }
+    runs
+}
+
+/// Where the emphasis closes in `text`, which follows an opening `delimiter`.
+/// Emphasis can't start or end next to a space, so `2 * 3 * 4` stays as it is.
+fn closing(text: &str, delimiter: &str) -> Option<usize> {
+    if text.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let mut from = 0;
+    while let Some(found) = text[from..].find(delimiter) {
+        let end = from + found;
+        // A single * looking for its partner steps over bold markers
+        if delimiter == "*" && text[end..].starts_with("**") {
+            from = end + 2;
+            continue;
+        }
+        if end > 0 && !text[..end].ends_with(char::is_whitespace) {
+            return Some(end);
+        }
+        from = end + delimiter.len();
+    }
+    None
+}
+
+/// Breaks styled text into lines that fit `first_width` columns and then
+/// `rest_width`, at spaces where it can and mid-word when a word is too long
+/// for a line of its own.
+fn wrap<'a>(runs: &[Run], first_width: usize, rest_width: usize) -> Vec<Vec<Span<'a>>> {
+    // Words keep their pieces' styles, since markup can change mid-word
+    let mut words: Vec<Vec<(&str, Style)>> = vec![Vec::new()];
+    for run in runs {
+        for (index, piece) in run.text.split(' ').enumerate() {
+            if index > 0 {
+                words.push(Vec::new());
+            }
+            if !piece.is_empty() {
+                words.last_mut().unwrap().push((piece, run.style));
+            }
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut line = Vec::new();
+    let mut line_width = 0;
+    let mut started = false;
+    let limit = |lines: &Vec<Vec<Span>>| {
+        if lines.is_empty() {
+            first_width
+        } else {
+            rest_width
+        }
+    };
+
+    for word in words {
+        let word_width: usize = word.iter().map(|(piece, _)| piece.width()).sum();
+        let gap = usize::from(started);
+        if line_width + gap + word_width <= limit(&lines) {
+            if started {
+                push_text(&mut line, " ", Style::default());
+            }
+            for (piece, style) in word {
+                push_text(&mut line, piece, style);
+            }
+            line_width += gap + word_width;
+            started = true;
+            continue;
+        }
+
+        if started {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0;
+        }
+        started = true;
+        for (piece, style) in word {
+            for ch in piece.chars() {
+                let ch_width = ch.width().unwrap_or(0);
+                if line_width + ch_width > limit(&lines) && line_width > 0 {
+                    lines.push(std::mem::take(&mut line));
+                    line_width = 0;
+                }
+                push_text(&mut line, ch.encode_utf8(&mut [0; 4]), style);
+                line_width += ch_width;
+            }
+        }
+    }
+    lines.push(line);
+
+    lines
+}
+
+/// A fenced code block in a box `width` columns wide, with long lines broken
+/// rather than wrapped at spaces.
+fn code_box<'a>(
+    language: Option<&str>,
+    code: &[String],
+    width: usize,
+    highlight: bool,
+    border: Style,
+) -> Vec<Line<'a>> {
+    let width = width.max(5);
+    let inner = width - 4;
+    let label = language
+        .map(|language| format!(" {language} "))
+        .unwrap_or_default();
+    let label_width = label.width().min(width - 2);
+
+    let mut lines = vec![Line::from(Span::styled(
+        format!("┌{label}{}┐", "─".repeat(width - 2 - label_width)),
+        border,
+    ))];
+
+    for source in code {
+        let runs = match language {
+            Some(language) if highlight => highlight_line(source, language),
+            _ => vec![Run {
+                text: source.clone(),
+                style: Style::default().fg(Color::LightCyan),
+            }],
+        };
+
+        // Hard-break to the box, then pad each row out to its right edge
+        let mut rows = vec![(Vec::new(), 0)];
+        for run in &runs {
+            for ch in run.text.chars() {
+                let ch_width = ch.width().unwrap_or(0);
+                let row_width = rows.last().map_or(0, |(_, row_width)| *row_width);
+                if row_width + ch_width > inner && row_width > 0 {
+                    rows.push((Vec::new(), 0));
+                }
+                let (row, row_width) = rows.last_mut().unwrap();
+                push_text(row, ch.encode_utf8(&mut [0; 4]), run.style);
+                *row_width += ch_width;
+            }
+        }
+
+        for (row, row_width) in rows {
+            let mut spans = vec![Span::styled("│ ", border)];
+            spans.extend(row);
+            spans.push(Span::raw(" ".repeat(inner.saturating_sub(row_width))));
+            spans.push(Span::styled(" │", border));
+            lines.push(Line::from(spans));
+        }
+    }
+
+    lines.push(Line::from(Span::styled(
+        format!("└{}┘", "─".repeat(width - 2)),
+        border,
+    )));
+    lines
+}
+
+/// A rough, language-agnostic colouring: keywords, strings, numbers and
+/// line comments.
+fn highlight_line(source: &str, language: &str) -> Vec<Run> {
+    let plain = Style::default().fg(Color::LightCyan);
+    let comment = match language {
+        "py" | "python" | "sh" | "bash" | "shell" | "rb" | "ruby" | "yaml" | "yml" | "toml" => "#",
+        "sql" | "lua" | "haskell" | "hs" => "--",
+        _ => "//",
+    };
+
+    let mut runs = Vec::new();
+    let mut rest = source;
+    while !rest.is_empty() {
+        let (len, style) = if rest.starts_with(comment) {
+            (rest.len(), Style::default().fg(Color::DarkGray))
+        } else if let Some(string) = rest.strip_prefix('"') {
+            let len = string.find('"').map_or(rest.len(), |end| end + 2);
+            (len, Style::default().fg(Color::Green))
+        } else {
+            let ch = rest.chars().next().unwrap();
+            if ch.is_alphanumeric() || ch == '_' {
+                let len = rest
+                    .find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+                    .unwrap_or(rest.len());
+                let word = &rest[..len];
+                let style = if KEYWORDS.contains(&word) {
+                    Style::default().fg(Color::Magenta)
+                } else if ch.is_ascii_digit() {
+                    Style::default().fg(Color::Yellow)
+                } else {
+                    plain
+                };
+                (len, style)
+            } else {
+                (ch.len_utf8(), plain)
+            }
+        };
+
+        runs.push(Run {
+            text: rest[..len].to_string(),
+            style,
+        });
+        rest = &rest[len..];
+    }
+    runs
+}
+
+/// Adds text to a line, joining it onto the last span when the style matches.
+fn push_text(line: &mut Vec<Span>, text: &str, style: Style) {
+    match line.last_mut() {
+        Some(last) if last.style == style => last.content.to_mut().push_str(text),
+        _ => line.push(Span::styled(text.to_string(), style)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runs(text: &str) -> Vec<(String, Style)> {
+        parse_inline(text, Style::default())
+            .into_iter()
+            .map(|run| (run.text, run.style))
+            .collect()
+    }
+
+    fn plain(text: &str) -> (String, Style) {
+        (text.to_string(), Style::default())
+    }
+
+    fn styled(text: &str, modifier: Modifier) -> (String, Style) {
+        (text.to_string(), Style::default().add_modifier(modifier))
+    }
+
+    fn wrapped(text: &str, first_width: usize, rest_width: usize) -> Vec<String> {
+        wrap(
+            &parse_inline(text, Style::default()),
+            first_width,
+            rest_width,
+        )
+        .iter()
+        .map(|spans| spans.iter().map(|span| span.content.as_ref()).collect())
+        .collect()
+    }
+
+    fn shown(lines: &[Line]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| {
+                line.spans
+                    .iter()
+                    .map(|span| span.content.as_ref())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn emphasis_and_code() {
+        let code = Style::default().fg(Color::LightCyan);
+        assert_eq!(
+            runs("a **b** *c* `d`"),
+            vec![
+                plain("a "),
+                styled("b", Modifier::BOLD),
+                plain(" "),
+                styled("c", Modifier::ITALIC),
+                plain(" "),
+                ("d".to_string(), code),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_emphasis() {
+        assert_eq!(
+            runs("**bold *both* bold**"),
+            vec![
+                styled("bold ", Modifier::BOLD),
+                styled("both", Modifier::BOLD | Modifier::ITALIC),
+                styled(" bold", Modifier::BOLD),
+            ]
+        );
+        assert_eq!(
+            runs("*it **both** it*"),
+            vec![
+                styled("it ", Modifier::ITALIC),
+                styled("both", Modifier::ITALIC | Modifier::BOLD),
+                styled(" it", Modifier::ITALIC),
+            ]
+        );
+    }
+
+    #[test]
+    fn code_spans_hide_markup() {
+        let code = Style::default().fg(Color::LightCyan);
+        assert_eq!(
+            runs("`**not bold**`"),
+            vec![("**not bold**".to_string(), code)]
+        );
+    }
+
+    #[test]
+    fn unclosed_markers_are_text() {
+        for text in [
+            "**bold",
+            "*italic",
+            "`code",
+            "2 * 3 * 4",
+            "** spaced**",
+            "a*",
+        ] {
+            assert_eq!(runs(text), vec![plain(text)], "{text}");
+        }
+    }
+
+    #[test]
+    fn closing_needs_text_on_both_sides() {
+        assert_eq!(closing("word*", "*"), Some(4));
+        assert_eq!(closing("two words**", "**"), Some(9));
+        assert_eq!(closing(" word*", "*"), None);
+        assert_eq!(closing("word *", "*"), None);
+        assert_eq!(closing("*", "*"), None);
+        assert_eq!(closing("", "**"), None);
+        // Steps past a space before one closing marker to find the next
+        assert_eq!(closing("a * b*", "*"), Some(5));
+        // A single marker isn't closed by half of a bold one
+        assert_eq!(closing("a**b*", "*"), Some(4));
+    }
+
+    #[test]
+    fn wraps_at_spaces() {
+        assert_eq!(
+            wrapped("hello there world", 11, 11),
+            ["hello there", "world"]
+        );
+        assert_eq!(wrapped("aa bb cc", 2, 5), ["aa", "bb cc"]);
+        assert_eq!(wrapped("", 5, 5), [""]);
+    }
+
+    #[test]
+    fn breaks_words_too_long_for_a_line() {
+        assert_eq!(wrapped("abcdefgh", 3, 3), ["abc", "def", "gh"]);
+        assert_eq!(wrapped("ab cdefgh", 4, 4), ["ab", "cdef", "gh"]);
+    }
+
+    #[test]
+    fn wraps_wide_characters_by_width() {
+        let lines = wrapped("日本語のテキスト", 5, 5);
+        assert_eq!(lines, ["日本", "語の", "テキ", "スト"]);
+        // Even a character wider than the line gets one of its own
+        assert_eq!(wrapped("日本", 1, 1), ["日", "本"]);
+    }
+
+    #[test]
+    fn words_keep_the_styles_of_their_pieces() {
+        let lines = wrap(&parse_inline("foo**bar** baz", Style::default()), 20, 20);
+        assert_eq!(lines.len(), 1);
+        let styles: Vec<_> = lines[0]
+            .iter()
+            .map(|span| (span.content.as_ref(), span.style))
+            .collect();
+        assert_eq!(
+            styles,
+            [
+                ("foo", Style::default()),
+                ("bar", Style::default().add_modifier(Modifier::BOLD)),
+                (" baz", Style::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn code_box_breaks_and_pads_lines() {
+        let code = ["abcdefghij".to_string(), "x".to_string()];
+        let lines = code_box(Some("rs"), &code, 8, false, Style::default());
+        assert_eq!(
+            shown(&lines),
+            [
+                "┌ rs ──┐",
+                "│ abcd │",
+                "│ efgh │",
+                "│ ij   │",
+                "│ x    │",
+                "└──────┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn code_box_fits_wide_characters() {
+        let lines = code_box(None, &["日本語".to_string()], 8, false, Style::default());
+        assert_eq!(
+            shown(&lines),
+            ["┌──────┐", "│ 日本 │", "│ 語   │", "└──────┘"]
+        );
+        assert!(lines.iter().all(|line| line.width() == 8));
+    }
+
+    #[test]
+    fn code_blocks_start_below_the_name() {
+        let lines = format_message("```\nlet x = 1;", Style::default(), 20, 20, false);
+        // An unclosed fence runs to the end of the message
+        assert_eq!(lines.len(), 4);
+        assert_eq!(shown(&lines)[0], "");
+        assert!(shown(&lines)[2].contains("let x = 1;"));
+    }
+}
@@ -0,0 +1,70 @@
+use crate::interact::{Message, Room};
+use crate::render::timeline::{message_lines, Entry, TimelineStyle};
+use chrono::Local;
+use ratatui::{
+    prelude::*,
+    style::{Color, Style},
+    text::Span,
+    widgets::*,
+};
+
+/// Highlighted messages from every room, newest first, each under the room
+/// and day it was sent in. `mentions` is kept oldest first.
+pub fn render_mentions<'a>(
+    mentions: &[Message],
+    rooms: &[Room],
+    selected: Option<i64>,
+    style: &TimelineStyle,
+) -> (List<'a>, ListState) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .style(Style::default().fg(Color::White))
+        .title("Mentions (Enter: go to message)")
+        .border_type(BorderType::Plain);
+
+    let hint = Style::default().fg(Color::DarkGray);
+    if mentions.is_empty() {
+        let items = vec![ListItem::new(Span::styled(
+            "Nothing yet. Messages that mention you or one of your keywords collect here.",
+            hint,
+        ))];
+        return (List::new(items).block(block), ListState::default());
+    }
+
+    let items: Vec<_> = mentions
+        .iter()
+        .rev()
+        .map(|message| {
+            let room = rooms
+                .iter()
+                .find(|room| room.id == message.message_room_id)
+                .map_or_else(
+                    || format!("room {}", message.message_room_id),
+                    |room| format!("#{}", room.title),
+                );
+            let mut heading = room;
+            if let Some(sent) = message.created_at {
+                let sent = sent.with_timezone(&Local);
+                heading.push_str(&format!(", {}", sent.format(&style.display.date_format)));
+            }
+            let heading_style = if selected == Some(message.id) {
+                hint.add_modifier(Modifier::REVERSED)
+            } else {
+                hint
+            };
+
+            let mut lines = vec![Line::from(Span::styled(heading, heading_style))];
+            lines.extend(message_lines(&Entry::from(message), style));
+            ListItem::new(lines)
+        })
+        .collect();
+
+    let mut state = ListState::default();
+    state.select(
+        mentions
+            .iter()
+            .rev()
+            .position(|message| Some(message.id) == selected),
+    );
+    (List::new(items).block(block), state)
+}
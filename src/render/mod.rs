@@ -1,3 +1,9 @@
 pub mod chat_room;
+pub mod composer;
+pub mod debug_log;
 pub mod friends;
 pub mod home;
+pub mod markup;
+pub mod mentions;
+pub mod status;
+pub mod timeline;
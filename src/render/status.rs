@@ -0,0 +1,99 @@
+use crate::structs::{Connection, Mode, Status};
+use ratatui::{
+    prelude::*,
+    style::{Color, Style},
+    text::Span,
+    widgets::{block::Title, *},
+};
+
+/// What the status bar shows besides the latest notification.
+pub struct StatusBar<'a> {
+    pub connection: &'a Connection,
+    pub username: Option<&'a str>,
+    /// The room or friend on screen, as `#room` or `@friend`.
+    pub conversation: Option<String>,
+    pub mode: Mode,
+}
+
+/// The latest notification, or a hint once it has faded, with the
+/// connection, user, conversation, mode and latency along the top border.
+pub fn render_status<'a>(status: Option<&Status>, bar: &StatusBar) -> Paragraph<'a> {
+    let line = match status {
+        Some(status) => Span::styled(
+            status.text.clone(),
+            Style::default().fg(match (status.error, status.fading()) {
+                (_, true) => Color::DarkGray,
+                (true, false) => Color::Red,
+                (false, false) => Color::White,
+            }),
+        ),
+        None => Span::styled(
+            "Type /help in the input box for commands",
+            Style::default().fg(Color::DarkGray),
+        ),
+    };
+
+    let separator = || Span::styled(" | ", Style::default().fg(Color::DarkGray));
+    let mut info = vec![connection_state(bar)];
+    if let Some(username) = bar.username {
+        info.push(separator());
+        info.push(Span::styled(
+            username.to_string(),
+            Style::default().fg(Color::LightBlue),
+        ));
+    }
+    if let Some(conversation) = &bar.conversation {
+        info.push(separator());
+        info.push(Span::raw(conversation.clone()));
+    }
+    info.push(separator());
+    info.push(match bar.mode {
+        Mode::Normal => Span::raw("NORMAL"),
+        Mode::Insert => Span::styled(
+            "INSERT",
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        ),
+    });
+    if let (Some(latency), None) = (bar.connection.latency, &bar.connection.unreachable) {
+        info.push(separator());
+        info.push(Span::raw(format!("{} ms", latency.as_millis())));
+    }
+    info.push(Span::raw(" "));
+
+    Paragraph::new(Line::from(line)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .style(Style::default().fg(Color::White))
+            .title("Status")
+            .title(Title::from(Line::from(info)).alignment(Alignment::Right))
+            .border_type(BorderType::Plain),
+    )
+}
+
+fn connection_state<'a>(bar: &StatusBar) -> Span<'a> {
+    let connection = bar.connection;
+    let (text, color) = if bar.username.is_none() {
+        ("not logged in".to_string(), Color::DarkGray)
+    } else if let Some(err) = &connection.unreachable {
+        (format!("offline: {err}"), Color::Red)
+    } else if connection.push {
+        ("connected".to_string(), Color::Green)
+    } else if let Some(err) = &connection.push_error {
+        (format!("reconnecting ({err})"), Color::Yellow)
+    } else {
+        ("connecting".to_string(), Color::Yellow)
+    };
+    Span::styled(shortened(text), Style::default().fg(color))
+}
+
+/// Network errors can run long; the rest of the bar matters more.
+fn shortened(mut text: String) -> String {
+    const MAX: usize = 60;
+    if let Some((end, _)) = text.char_indices().nth(MAX) {
+        text.truncate(end);
+        text.push_str("...");
+    }
+    text
+}
@@ -0,0 +1,263 @@
+use crate::alerts::is_highlighted;
+use crate::config::DisplayConfig;
+use crate::interact::{DirectMessage, Message};
+use crate::outbox::{Delivery, Outgoing};
+use crate::render::markup::format_message;
+use chrono::{DateTime, Days, Local, NaiveDate, Utc};
+use ratatui::{
+    prelude::*,
+    style::{Color, Modifier, Style},
+    text::Span,
+    widgets::*,
+};
+use unicode_width::UnicodeWidthStr;
+
+/// Colours handed out to names; red and grey are left for errors and hints.
+const NAME_COLORS: [Color; 10] = [
+    Color::Cyan,
+    Color::Green,
+    Color::Magenta,
+    Color::Blue,
+    Color::LightCyan,
+    Color::LightGreen,
+    Color::LightMagenta,
+    Color::LightBlue,
+    Color::LightYellow,
+    Color::LightRed,
+];
+
+/// The parts of a room or private message the chat panes show.
+pub struct Entry<'b> {
+    pub id: i64,
+    pub author: &'b str,
+    pub text: &'b str,
+    pub created_at: Option<DateTime<Utc>>,
+    pub edited_at: Option<DateTime<Utc>>,
+    /// Set for our own messages that the server hasn't sent back yet.
+    pub delivery: Option<&'b Delivery>,
+}
+
+impl<'b> Entry<'b> {
+    /// A message still in the outbox, written by `author`.
+    pub fn outgoing(message: &'b Outgoing, author: &'b str) -> Self {
+        Entry {
+            id: 0,
+            author,
+            text: &message.text,
+            created_at: Some(message.created_at),
+            edited_at: None,
+            delivery: Some(&message.delivery),
+        }
+    }
+}
+
+impl<'b> From<&'b Message> for Entry<'b> {
+    fn from(message: &'b Message) -> Self {
+        Entry {
+            id: message.id,
+            author: &message.message_user_name,
+            text: &message.message_text,
+            created_at: message.created_at,
+            edited_at: message.edited_at,
+            delivery: None,
+        }
+    }
+}
+
+impl<'b> From<&'b DirectMessage> for Entry<'b> {
+    fn from(message: &'b DirectMessage) -> Self {
+        Entry {
+            id: message.id,
+            author: &message.sender_user_name,
+            text: &message.message_text,
+            created_at: message.created_at,
+            edited_at: message.edited_at,
+            delivery: None,
+        }
+    }
+}
+
+/// What laying out a conversation depends on besides the messages.
+pub struct TimelineStyle<'b> {
+    pub display: &'b DisplayConfig,
+    /// The logged in user, whose own messages stand out.
+    pub username: &'b str,
+    /// Words besides the username that highlight someone else's message.
+    pub keywords: &'b [String],
+    /// Columns available inside the pane's borders.
+    pub width: u16,
+    /// The last message read before this visit; a divider marks what came after.
+    pub unread_after: Option<i64>,
+}
+
+/// Lays out a conversation as list rows, with a separator wherever the local
+/// date changes and above the first unread message. Also returns which row
+/// each message ended up on.
+pub fn timeline<'a, 'b>(
+    entries: impl IntoIterator<Item = Entry<'b>>,
+    style: &TimelineStyle,
+) -> (Vec<ListItem<'a>>, Vec<usize>) {
+    let dim = Style::default().fg(Color::DarkGray);
+    let today = Local::now().date_naive();
+
+    let mut items = Vec::new();
+    let mut rows = Vec::new();
+    let mut last_day: Option<NaiveDate> = None;
+    let mut divided = false;
+
+    for entry in entries {
+        let sent = entry.created_at.map(|time| time.with_timezone(&Local));
+
+        if let Some(day) = sent.map(|time| time.date_naive()) {
+            if last_day != Some(day) {
+                items.push(separator(day_label(day, today, style.display), dim));
+                last_day = Some(day);
+            }
+        }
+
+        let unread = style.unread_after.is_some_and(|read| entry.id > read);
+        if unread && !divided {
+            items.push(separator(
+                "New messages".to_string(),
+                Style::default().fg(Color::LightRed),
+            ));
+            divided = true;
+        }
+
+        rows.push(items.len());
+        items.push(ListItem::new(message_lines(&entry, style)));
+    }
+
+    (items, rows)
+}
+
+/// One message, wrapped to the pane with its text indented past the name.
+/// Messages from others that mention us get their name picked out in yellow.
+pub fn message_lines<'a>(entry: &Entry, style: &TimelineStyle) -> Vec<Line<'a>> {
+    let dim = Style::default().fg(Color::DarkGray);
+    let own = entry.author == style.username;
+    let highlighted = !own && is_highlighted(entry.text, style.username, style.keywords);
+    let sent = entry.created_at.map(|time| time.with_timezone(&Local));
+
+    let mut prefix = Vec::new();
+    if let Some(sent) = sent {
+        prefix.push(Span::styled(
+            format!("{} ", sent.format(&style.display.time_format)),
+            dim,
+        ));
+    }
+    let mut name_style = Style::default().fg(name_color(entry.author));
+    let mut text_style = Style::default();
+    if own {
+        name_style = name_style.add_modifier(Modifier::BOLD);
+        text_style = text_style.fg(Color::Yellow);
+    } else if highlighted {
+        name_style = Style::default()
+            .fg(Color::Black)
+            .bg(Color::Yellow)
+            .add_modifier(Modifier::BOLD);
+        text_style = text_style.add_modifier(Modifier::BOLD);
+    }
+    // Keeps the highlight to the name itself
+    let punctuation = if highlighted {
+        Style::default().fg(Color::Yellow)
+    } else {
+        name_style
+    };
+    // "/me waves" reads as "* name waves"
+    let text = match entry.text.strip_prefix("/me ") {
+        Some(action) => {
+            prefix.push(Span::styled("* ", punctuation));
+            prefix.push(Span::styled(entry.author.to_string(), name_style));
+            prefix.push(Span::from(" "));
+            text_style = text_style.add_modifier(Modifier::ITALIC);
+            action
+        }
+        None => {
+            prefix.push(Span::styled(entry.author.to_string(), name_style));
+            prefix.push(Span::styled(": ", punctuation));
+            entry.text
+        }
+    };
+
+    // Hang the text under where it starts, unless the name eats most of the pane
+    let width = usize::from(style.width).max(1);
+    let prefix_width: usize = prefix.iter().map(Span::width).sum();
+    let indent = if prefix_width <= width / 2 {
+        prefix_width
+    } else {
+        2
+    };
+    let first_width = width.saturating_sub(prefix_width).max(1);
+    let rest_width = width.saturating_sub(indent).max(1);
+
+    // Notes after the text, each moved to a line of its own if it won't fit
+    let mut notes = Vec::new();
+    if let Some(edited) = entry.edited_at {
+        let edited = edited.with_timezone(&Local);
+        let note = format!(" (edited {})", edited.format(&style.display.time_format));
+        notes.push((note, dim));
+    }
+    match entry.delivery {
+        Some(Delivery::Pending) => notes.push((" (sending...)".to_string(), dim)),
+        Some(Delivery::Failed(error)) => notes.push((
+            format!(" (not sent, s to retry or x to discard: {error})"),
+            Style::default().fg(Color::Red),
+        )),
+        Some(Delivery::Sent) | None => {}
+    }
+
+    let mut lines = format_message(
+        text,
+        text_style,
+        first_width,
+        rest_width,
+        style.display.highlight_code,
+    );
+    for (index, line) in lines.iter_mut().enumerate() {
+        let lead = if index == 0 {
+            prefix.clone()
+        } else {
+            vec![Span::from(" ".repeat(indent))]
+        };
+        line.spans.splice(0..0, lead);
+    }
+
+    for (note, note_style) in notes {
+        let Some(last) = lines.last_mut() else {
+            break;
+        };
+        if last.width() + note.width() <= width {
+            last.spans.push(Span::styled(note, note_style));
+        } else {
+            lines.push(Line::from(vec![
+                Span::from(" ".repeat(indent)),
+                Span::styled(note.trim_start().to_string(), note_style),
+            ]));
+        }
+    }
+
+    lines
+}
+
+/// The same colour for a name every time, picked from its bytes.
+fn name_color(name: &str) -> Color {
+    let hash = name.bytes().fold(0usize, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(byte.into())
+    });
+    NAME_COLORS[hash % NAME_COLORS.len()]
+}
+
+fn day_label(day: NaiveDate, today: NaiveDate, display: &DisplayConfig) -> String {
+    if day == today {
+        "Today".to_string()
+    } else if Some(day) == today.checked_sub_days(Days::new(1)) {
+        "Yesterday".to_string()
+    } else {
+        day.format(&display.date_format).to_string()
+    }
+}
+
+fn separator<'a>(label: String, style: Style) -> ListItem<'a> {
+    ListItem::new(Span::styled(format!("── {label} ──"), style))
+}
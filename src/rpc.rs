@@ -0,0 +1,184 @@
+use crate::config::ServerConfig;
+use crate::logging::redacted;
+use crate::structs::Error;
+use reqwest::{Client, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    sync::atomic::{AtomicI64, Ordering},
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+use tracing::{debug, warn};
+
+/// How much of a response body the log keeps.
+const LOGGED_BODY: usize = 2000;
+
+/// A JSON-RPC method the server exposes: the request struct is the `params`,
+/// and `Output` is what comes back in `result`.
+pub trait RpcMethod: Serialize {
+    const METHOD: &'static str;
+    type Output: DeserializeOwned;
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    jsonrpc: &'static str,
+    id: i64,
+    method: &'a str,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct Response<R> {
+    result: Option<R>,
+    error: Option<ErrorObject>,
+}
+
+#[derive(Deserialize)]
+struct ErrorObject {
+    #[serde(default)]
+    code: i64,
+    message: String,
+}
+
+/// Talks JSON-RPC 2.0 to one server over the shared HTTP client.
+pub struct RpcClient {
+    http: Client,
+    server: ServerConfig,
+    next_id: AtomicI64,
+    /// Woken once a call is turned away for not being logged in.
+    expired: Notify,
+}
+
+impl RpcClient {
+    pub fn new(http: Client, server: ServerConfig) -> Self {
+        RpcClient {
+            http,
+            server,
+            next_id: AtomicI64::new(1),
+            expired: Notify::new(),
+        }
+    }
+
+    pub fn http(&self) -> &Client {
+        &self.http
+    }
+
+    pub fn server(&self) -> &ServerConfig {
+        &self.server
+    }
+
+    /// Waits until the server stops accepting the session.
+    pub async fn session_expired(&self) {
+        self.expired.notified().await
+    }
+
+    pub async fn call<M: RpcMethod>(&self, params: &M) -> Result<M::Output, Error> {
+        self.send(params, None).await
+    }
+
+    /// Like [`call`](Self::call), for methods the server may hold open
+    /// (long polls) longer than a normal request should take.
+    pub async fn call_with_timeout<M: RpcMethod>(
+        &self,
+        params: &M,
+        timeout: Duration,
+    ) -> Result<M::Output, Error> {
+        self.send(params, Some(timeout)).await
+    }
+
+    async fn send<M: RpcMethod>(
+        &self,
+        params: &M,
+        timeout: Option<Duration>,
+    ) -> Result<M::Output, Error> {
+        let request = Request {
+            jsonrpc: "2.0",
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            method: M::METHOD,
+            params: serde_json::to_value(params)
+                .map_err(|err| Error::DecodeError(err.to_string()))?,
+        };
+        debug!(id = request.id, params = %redacted(&request.params), "-> {}", M::METHOD);
+
+        let started = Instant::now();
+        let result = self.exchange::<M>(&request, timeout).await;
+        if let Err(err) = &result {
+            warn!(id = request.id, elapsed = ?started.elapsed(), "{} failed: {err}", M::METHOD);
+        }
+        result
+    }
+
+    async fn exchange<M: RpcMethod>(
+        &self,
+        request: &Request<'_>,
+        timeout: Option<Duration>,
+    ) -> Result<M::Output, Error> {
+        let started = Instant::now();
+        let mut builder = self.http.post(self.server.rpc_url()).json(&request);
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+        let response = builder.send().await?;
+
+        let status = response.status();
+        if status == StatusCode::UNAUTHORIZED {
+            self.expired.notify_one();
+            return Err(Error::SessionExpired);
+        }
+        let body = response.bytes().await?;
+        debug!(
+            id = request.id,
+            %status,
+            elapsed = ?started.elapsed(),
+            body = %logged_body(&body),
+            "<- {}",
+            M::METHOD
+        );
+
+        // Servers send JSON-RPC errors with non-2xx statuses too, so look for
+        // an error object before falling back to the bare status
+        let decoded = serde_json::from_slice::<Response<M::Output>>(&body);
+        match decoded {
+            Ok(Response {
+                error: Some(error), ..
+            }) => Err(Error::RpcError {
+                code: error.code,
+                message: error.message,
+            }),
+            _ if !status.is_success() => Err(Error::HttpStatusError(status)),
+            Ok(Response {
+                result: Some(result),
+                ..
+            }) => Ok(result),
+            // A missing result is fine for methods that return nothing
+            Ok(_) => serde_json::from_value(Value::Null).map_err(|_| {
+                Error::DecodeError(format!(
+                    "{} response had neither result nor error",
+                    M::METHOD
+                ))
+            }),
+            Err(err) => Err(Error::DecodeError(format!("{}: {err}", M::METHOD))),
+        }
+    }
+}
+
+/// A response body for the log: secrets blanked out if it's JSON, and cut
+/// short if it's long.
+fn logged_body(body: &[u8]) -> String {
+    let mut text = match serde_json::from_slice::<Value>(body) {
+        Ok(value) => redacted(&value),
+        Err(_) => String::from_utf8_lossy(body).into_owned(),
+    };
+    if text.len() > LOGGED_BODY {
+        let mut end = LOGGED_BODY;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("...");
+    }
+    text
+}
@@ -0,0 +1,161 @@
+use crate::config::state_dir;
+use crate::structs::Event;
+use chrono::Local;
+use crossterm::{
+    cursor::Show,
+    event::{
+        DisableMouseCapture, KeyEvent, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+        PushKeyboardEnhancementFlags,
+    },
+    execute,
+    terminal::{
+        disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
+};
+use std::{
+    backtrace::Backtrace,
+    fs, io,
+    panic::{self, PanicHookInfo},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+    },
+    thread,
+};
+
+/// Whether the terminal is set up for the UI, so that restoring it twice,
+/// say on a panic during teardown, does no harm.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static ENHANCED_KEYS: AtomicBool = AtomicBool::new(false);
+
+/// Keeps the terminal in raw mode on the alternate screen, and puts it back
+/// as it was when dropped, however `main` ends.
+pub struct Guard;
+
+impl Guard {
+    pub fn enter() -> io::Result<Self> {
+        enter()?;
+        Ok(Guard)
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+fn enter() -> io::Result<()> {
+    enable_raw_mode()?;
+    ACTIVE.store(true, Ordering::SeqCst);
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    // Lets terminals that can tell Shift+Enter apart from Enter say so
+    if supports_keyboard_enhancement().unwrap_or(false) {
+        execute!(
+            stdout,
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+        )?;
+        ENHANCED_KEYS.store(true, Ordering::SeqCst);
+    }
+    Ok(())
+}
+
+/// Gives the terminal back to the shell. Errors are ignored, since this
+/// also runs while panicking, with nowhere left to report them.
+pub fn restore() {
+    if !ACTIVE.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    let mut stdout = io::stdout();
+    if ENHANCED_KEYS.swap(false, Ordering::SeqCst) {
+        let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+    }
+    let _ = execute!(stdout, LeaveAlternateScreen, DisableMouseCapture, Show);
+    let _ = disable_raw_mode();
+}
+
+/// Does what Ctrl+Z does outside raw mode: hands the terminal back and
+/// stops until the shell continues us, then takes it again. The caller has
+/// to redraw everything afterwards.
+#[cfg(unix)]
+pub fn suspend() -> io::Result<()> {
+    restore();
+    // SAFETY: raise has no preconditions; SIGTSTP just stops the process
+    unsafe {
+        libc::raise(libc::SIGTSTP);
+    }
+    enter()
+}
+
+/// There's no job control to hand over to.
+#[cfg(not(unix))]
+pub fn suspend() -> io::Result<()> {
+    Ok(())
+}
+
+/// Restores the terminal before the panic message is printed, so it can be
+/// read, and saves a crash report. Then exits: a panic on any thread leaves
+/// the UI unable to carry on.
+pub fn install_panic_hook() {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // Keeps the UI thread from drawing over the message until we exit
+        let _stdout = io::stdout().lock();
+        restore();
+        default(info);
+        match write_crash_report(info) {
+            Ok(path) => eprintln!("A crash report was saved to {}", path.display()),
+            Err(err) => eprintln!("Couldn't save a crash report: {err}"),
+        }
+        std::process::exit(101);
+    }));
+}
+
+fn write_crash_report(info: &PanicHookInfo) -> io::Result<PathBuf> {
+    let now = Local::now();
+    let report = format!(
+        "tui {} crashed at {}\nthread: {}\n\n{info}\n\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        now.to_rfc3339(),
+        thread::current().name().unwrap_or("<unnamed>"),
+        Backtrace::force_capture(),
+    );
+    let dir = state_dir().join("crashes");
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("crash-{}.txt", now.format("%Y%m%d-%H%M%S")));
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+/// Sends `Event::Quit` on SIGINT, SIGTERM or SIGHUP, so being killed tears
+/// down like quitting does.
+#[cfg(unix)]
+pub async fn quit_signals(events: Sender<Event<KeyEvent>>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let kinds = [
+        SignalKind::interrupt(),
+        SignalKind::terminate(),
+        SignalKind::hangup(),
+    ];
+    let Ok(mut signals) = kinds
+        .into_iter()
+        .map(signal)
+        .collect::<io::Result<Vec<_>>>()
+    else {
+        return;
+    };
+    let received = signals.iter_mut().map(|signal| Box::pin(signal.recv()));
+    futures_util::future::select_all(received).await;
+    let _ = events.send(Event::Quit);
+}
+
+#[cfg(not(unix))]
+pub async fn quit_signals(events: Sender<Event<KeyEvent>>) {
+    if tokio::signal::ctrl_c().await.is_ok() {
+        let _ = events.send(Event::Quit);
+    }
+}
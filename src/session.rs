@@ -0,0 +1,143 @@
+use crate::config::{account_dir, server_dir, ServerConfig};
+use crate::structs::Error;
+use reqwest::{
+    cookie::{CookieStore, Jar},
+    Url,
+};
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::PathBuf, time::SystemTime};
+
+/// An account that has logged in to a server from here: who it is, and the
+/// cookies the server recognises them by while they stay logged in.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedSession {
+    pub username: String,
+    /// As sent in a `Cookie` header to the RPC endpoint; `None` once logged
+    /// out or turned away.
+    cookies: Option<String>,
+}
+
+impl SavedSession {
+    /// Whether it can log in again without a password.
+    pub fn resumable(&self) -> bool {
+        self.cookies.is_some()
+    }
+
+    /// Puts the saved cookies back in `jar`, scoped as they were sent.
+    pub fn restore(&self, jar: &Jar, server: &ServerConfig) {
+        let (Some(cookies), Ok(url)) = (&self.cookies, Url::parse(&server.rpc_url())) else {
+            return;
+        };
+        for cookie in cookies.split(';') {
+            jar.add_cookie_str(cookie.trim(), &url);
+        }
+    }
+}
+
+fn path(server: &ServerConfig, username: &str) -> PathBuf {
+    account_dir(server, username).join("session.json")
+}
+
+/// Every account saved for `server`, the one logged in to most recently
+/// first. Files that can't be read are left out rather than hiding the rest.
+pub fn accounts(server: &ServerConfig) -> Vec<SavedSession> {
+    let Ok(dirs) = fs::read_dir(server_dir(server)) else {
+        return Vec::new();
+    };
+    let mut accounts: Vec<(SystemTime, SavedSession)> = dirs
+        .flatten()
+        .filter_map(|dir| {
+            let path = dir.path().join("session.json");
+            let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok()?;
+            let saved = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+            Some((modified, saved))
+        })
+        .collect();
+    accounts.sort_by(|(a, _), (b, _)| b.cmp(a));
+    accounts.into_iter().map(|(_, saved)| saved).collect()
+}
+
+/// Saves the cookies `jar` holds for `server`, so the next run can skip
+/// logging in as `username`.
+pub fn save(jar: &Jar, server: &ServerConfig, username: &str) -> Result<(), Error> {
+    let url = Url::parse(&server.rpc_url()).map_err(|err| Error::ConfigError(err.to_string()))?;
+    let cookies = jar
+        .cookies(&url)
+        .map(|cookies| cookies.to_str().map(String::from))
+        .transpose()
+        .map_err(|err| Error::DecodeError(err.to_string()))?;
+    write(
+        server,
+        &SavedSession {
+            username: username.to_string(),
+            cookies,
+        },
+    )
+}
+
+/// Drops the session saved for `username` once it's no good, keeping the
+/// account on the list.
+pub fn forget(server: &ServerConfig, username: &str) -> Result<(), Error> {
+    write(
+        server,
+        &SavedSession {
+            username: username.to_string(),
+            cookies: None,
+        },
+    )
+}
+
+/// Moves everything kept for `old` over to `new` after a rename: history,
+/// read state, unsent messages and the saved session. Whatever is already
+/// kept under `new` belonged to an earlier account of that name, so rather
+/// than mix the two this fails and leaves both where they are.
+pub fn rename(server: &ServerConfig, old: &str, new: &str) -> Result<(), Error> {
+    let (from, to) = (account_dir(server, old), account_dir(server, new));
+    if to != from {
+        if to.exists() {
+            let err = format!("{} is already there", to.display());
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, err).into());
+        }
+        if from.exists() {
+            fs::rename(&from, &to)?;
+        }
+    }
+    // The session came along, but still says who it was saved for
+    match read(server, new) {
+        Some(saved) => write(
+            server,
+            &SavedSession {
+                username: new.to_string(),
+                ..saved
+            },
+        ),
+        None => Ok(()),
+    }
+}
+
+fn read(server: &ServerConfig, username: &str) -> Option<SavedSession> {
+    serde_json::from_str(&fs::read_to_string(path(server, username)).ok()?).ok()
+}
+
+/// Only the user can read the file, since the cookies in it are as good as
+/// their password until the server forgets the session.
+fn write(server: &ServerConfig, saved: &SavedSession) -> Result<(), Error> {
+    let path = path(server, &saved.username);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode above only applies to new files
+        if path.exists() {
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    let file = options.open(&path)?;
+    serde_json::to_writer(file, saved)?;
+    Ok(())
+}
@@ -1,27 +1,40 @@
+use crate::interact::{DirectMessage, FriendRequest, Message, Room, ServerInfo};
 use serde::{Deserialize, Serialize};
-use std::io;
+use std::{
+    io,
+    time::{Duration, Instant},
+};
 use thiserror::Error;
 
-#[derive(Serialize, Deserialize, Clone)]
-pub struct User {
-    pub id: i64,
-    pub username: String,
-    pub friends: Vec<String>,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-pub struct Chat {
-    pub id: i64,
-    pub username: String,
-    pub text: String,
+/// Something the user can be chatting in: a room or a private conversation.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Conversation {
+    Room(i64),
+    Direct(String),
 }
 
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error("error reading the DB file: {0}")]
-    ReadDBError(#[from] io::Error),
-    #[error("error parsing the DB file: {0}")]
-    ParseDBError(#[from] serde_json::Error),
+    #[error("couldn't access a local file: {0}")]
+    IoError(#[from] io::Error),
+    #[error("a local file is damaged: {0}")]
+    StorageError(#[from] serde_json::Error),
+    #[error("error contacting the server: {0}")]
+    NetworkError(#[from] reqwest::Error),
+    #[error("server responded with {0}")]
+    HttpStatusError(reqwest::StatusCode),
+    #[error("server error {code}: {message}")]
+    RpcError { code: i64, message: String },
+    #[error("unexpected response from the server: {0}")]
+    DecodeError(String),
+    #[error("{0}")]
+    AuthError(String),
+    #[error("your session has expired, please log in again")]
+    SessionExpired,
+    #[error("error loading the config file {0}")]
+    ConfigError(String),
+    #[error("{0}")]
+    ArgsError(String),
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -29,13 +42,191 @@ pub enum MenuItem {
     Home,
     ChatRooms,
     FriendsList,
+    Mentions,
 }
 
 pub enum Event<I> {
     Input(I),
     Tick,
+    /// `offline` says why the server couldn't be reached, when all there is
+    /// to show is what's cached for `username`.
+    LoggedIn {
+        username: String,
+        offline: Option<String>,
+    },
+    LoginFailed(String),
+    /// Logged in, but the session couldn't be saved for next time.
+    SessionNotSaved(Error),
+    /// The server stopped accepting our session; the user has to log in again.
+    SessionExpired,
+    /// Logged out, with whether the saved session could be cleared.
+    LoggedOut(Result<(), Error>),
+    ServerInfo(ServerInfo),
+    /// The outcome of `/nick`: the new name, or why it was refused.
+    Renamed(Result<String, Error>),
+    Rooms(Result<Vec<Room>, Error>),
+    /// A room we just created, so it can be selected.
+    RoomCreated(Room),
+    /// The server says the room list or memberships changed.
+    RoomsChanged,
+    RoomActionFailed(Error),
+    /// The latest page of a room, or with `since_id` only what's newer.
+    Messages {
+        room_id: i64,
+        since_id: Option<i64>,
+        messages: Result<Vec<Message>, Error>,
+    },
+    OlderMessages {
+        room_id: i64,
+        messages: Result<Vec<Message>, Error>,
+    },
+    NewMessage(Message),
+    DirectMessages {
+        friend: String,
+        messages: Result<Vec<DirectMessage>, Error>,
+    },
+    NewDirectMessage(DirectMessage),
+    Friends(Result<Vec<String>, Error>),
+    FriendRequests(Result<Vec<FriendRequest>, Error>),
+    /// The server says our friends or requests changed.
+    FriendsChanged,
+    FriendActionFailed(Error),
+    /// The server accepted the outgoing message with this client id.
+    Sent(String),
+    SendFailed {
+        client_id: String,
+        error: Error,
+    },
+    /// New messages are being pushed over the WebSocket.
+    PushConnected,
+    /// The WebSocket went away, with why; messages are polled for until it's back.
+    PushLost(String),
+    /// How long the latest heartbeat took, or why it didn't get through.
+    Heartbeat(Result<Duration, Error>),
+    /// Asked to stop from outside, such as by a signal.
+    Quit,
+}
+
+/// Where the user is in a room's history. `offset` counts messages up from
+/// the newest one, so 0 keeps the view stuck to the bottom as messages arrive.
+#[derive(Default, Clone, Copy)]
+pub struct Scrollback {
+    pub offset: usize,
+    /// An older page has been asked for and hasn't come back yet.
+    pub loading: bool,
+    /// The server has nothing older than what's loaded.
+    pub reached_start: bool,
+}
+
+impl Scrollback {
+    /// Moves `by` messages towards the start of the `len` loaded, stopping
+    /// at the oldest.
+    pub fn up(&mut self, by: usize, len: usize) {
+        self.offset = (self.offset + by).min(len.saturating_sub(1));
+    }
+
+    pub fn down(&mut self, by: usize) {
+        self.offset = self.offset.saturating_sub(by);
+    }
+
+    /// Scrolls so the message at `index` of the `len` loaded is the newest
+    /// one on screen.
+    pub fn show(&mut self, index: usize, len: usize) {
+        self.offset = len.saturating_sub(index + 1);
+    }
+
+    /// Keeps the same messages on screen when one arrives below them.
+    pub fn message_added(&mut self) {
+        if self.offset > 0 {
+            self.offset += 1;
+        }
+    }
+
+    /// Whether the top of a `page` high view is close enough to the start
+    /// of the `len` loaded messages to fetch the page before them.
+    pub fn wants_older(&self, page: usize, len: usize) -> bool {
+        !self.loading && !self.reached_start && self.offset + page >= len
+    }
+}
+
+/// A one-line message for the status line, such as a command's outcome.
+/// It fades once it's been up a while, errors more slowly.
+pub struct Status {
+    pub text: String,
+    pub error: bool,
+    pub shown_at: Instant,
+}
+
+impl Status {
+    const INFO_FOR: Duration = Duration::from_secs(5);
+    const ERROR_FOR: Duration = Duration::from_secs(15);
+
+    pub fn info(text: impl Into<String>) -> Self {
+        Status {
+            text: text.into(),
+            error: false,
+            shown_at: Instant::now(),
+        }
+    }
+
+    pub fn error(text: impl Into<String>) -> Self {
+        Status {
+            text: text.into(),
+            error: true,
+            shown_at: Instant::now(),
+        }
+    }
+
+    /// How long it stays up.
+    fn lifetime(&self) -> Duration {
+        match self.error {
+            true => Self::ERROR_FOR,
+            false => Self::INFO_FOR,
+        }
+    }
+
+    /// In its last couple of seconds, when it's shown dimmed.
+    pub fn fading(&self) -> bool {
+        self.shown_at.elapsed() + Duration::from_secs(2) >= self.lifetime()
+    }
+
+    pub fn expired(&self) -> bool {
+        self.shown_at.elapsed() >= self.lifetime()
+    }
+}
+
+/// How the link to the server is doing, for the status bar.
+#[derive(Default)]
+pub struct Connection {
+    /// Messages are pushed over the WebSocket rather than polled for.
+    pub push: bool,
+    /// Why the WebSocket last went away, while it's down.
+    pub push_error: Option<String>,
+    /// Why the server couldn't be reached, until it can again.
+    pub unreachable: Option<String>,
+    /// Round trip of the latest heartbeat.
+    pub latency: Option<Duration>,
+}
+
+/// What the input box is asking for when it isn't a chat message.
+#[derive(Copy, Clone)]
+pub enum Prompt {
+    AddFriend,
+    CreateRoom,
+    RenameRoom(i64),
+}
+
+impl Prompt {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Prompt::AddFriend => "Add friend: enter their username",
+            Prompt::CreateRoom => "New room: enter a title",
+            Prompt::RenameRoom(_) => "Rename room: enter the new title",
+        }
+    }
 }
 
+#[derive(Copy, Clone)]
 pub enum Mode {
     Normal,
     Insert,
@@ -47,6 +238,7 @@ impl From<MenuItem> for usize {
             MenuItem::Home => 0,
             MenuItem::ChatRooms => 1,
             MenuItem::FriendsList => 2,
+            MenuItem::Mentions => 3,
         }
     }
 }
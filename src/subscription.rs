@@ -0,0 +1,171 @@
+use crate::config::ServerConfig;
+use crate::interact::{self, Push};
+use crate::rpc::RpcClient;
+use crate::structs::{Conversation, Event};
+use crossterm::event::KeyEvent;
+use futures_util::StreamExt;
+use reqwest::cookie::{CookieStore, Jar};
+use std::{
+    sync::{mpsc::Sender, Arc},
+    time::Duration,
+};
+use tokio::{sync::watch, time::Instant};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{client::IntoClientRequest, http::HeaderValue, Message as WsMessage},
+};
+use tracing::{debug, info};
+
+/// How long to fall back to polling before trying the WebSocket again.
+const WS_RETRY: Duration = Duration::from_secs(30);
+/// Pause between polls when the server answers straight away instead of
+/// holding the request open.
+const POLL_DELAY: Duration = Duration::from_secs(2);
+
+/// The conversation the user is looking at and the newest message id they already have.
+pub type Watched = Option<(Conversation, i64)>;
+
+/// Feeds new messages to the UI for as long as the session lasts: over the
+/// server's WebSocket when it has one, otherwise by long-polling the watched
+/// conversation with a "since id" cursor.
+pub async fn run(
+    rpc: Arc<RpcClient>,
+    jar: Arc<Jar>,
+    mut watched: watch::Receiver<Watched>,
+    events: Sender<Event<KeyEvent>>,
+) {
+    // Conversation being polled and the newest id seen in it
+    let mut cursor: Watched = None;
+
+    loop {
+        // Any error just means falling back to polling for a while
+        if let Err(err) = listen(&jar, rpc.server(), &events).await {
+            info!("push connection lost ({err}), polling for {WS_RETRY:?}");
+            let _ = events.send(Event::PushLost(err));
+        }
+
+        let retry_at = Instant::now() + WS_RETRY;
+        while Instant::now() < retry_at {
+            cursor = match (cursor, watched.borrow_and_update().clone()) {
+                (Some((conversation, since_id)), Some((latest, latest_since)))
+                    if conversation == latest =>
+                {
+                    Some((conversation, since_id.max(latest_since)))
+                }
+                (_, latest) => latest,
+            };
+
+            let Some((conversation, since_id)) = cursor.clone() else {
+                match tokio::time::timeout_at(retry_at, watched.changed()).await {
+                    // The network task is gone, and with it anyone to follow for
+                    Ok(Err(_)) => return,
+                    _ => continue,
+                }
+            };
+
+            tokio::select! {
+                newest = poll(&rpc, &conversation, since_id, &events) => {
+                    // Errors are retried like an empty poll
+                    match newest {
+                        Some(newest) => cursor = Some((conversation, since_id.max(newest))),
+                        None => tokio::time::sleep(POLL_DELAY).await,
+                    }
+                }
+                // Switching conversations shouldn't wait for a long poll to come back
+                changed = watched.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Asks for anything newer than `since_id` and forwards it to the UI.
+/// Returns the newest id received, if there was anything.
+async fn poll(
+    rpc: &RpcClient,
+    conversation: &Conversation,
+    since_id: i64,
+    events: &Sender<Event<KeyEvent>>,
+) -> Option<i64> {
+    match conversation {
+        Conversation::Room(room_id) => {
+            let messages = interact::get_messages_since(rpc, *room_id, since_id)
+                .await
+                .unwrap_or_default();
+            let newest = messages.iter().map(|message| message.id).max();
+            for message in messages {
+                let _ = events.send(Event::NewMessage(message));
+            }
+            newest
+        }
+        Conversation::Direct(friend) => {
+            let messages = interact::get_direct_messages_since(rpc, friend, since_id)
+                .await
+                .unwrap_or_default();
+            let newest = messages.iter().map(|message| message.id).max();
+            for message in messages {
+                let _ = events.send(Event::NewDirectMessage(message));
+            }
+            newest
+        }
+    }
+}
+
+/// Reads pushed frames until the socket closes. Returns why it stopped.
+async fn listen(
+    jar: &Jar,
+    server: &ServerConfig,
+    events: &Sender<Event<KeyEvent>>,
+) -> Result<(), String> {
+    let mut request = server
+        .ws_url()
+        .into_client_request()
+        .map_err(|err| err.to_string())?;
+
+    // Authenticate the socket with the session cookie from login, looked up
+    // where it's saved and restored, since a restored one is scoped to that path
+    if let Some(cookies) = server
+        .rpc_url()
+        .parse()
+        .ok()
+        .and_then(|url| jar.cookies(&url))
+    {
+        if let Ok(value) = HeaderValue::from_bytes(cookies.as_bytes()) {
+            request.headers_mut().insert("Cookie", value);
+        }
+    }
+
+    let (mut socket, _) = connect_async(request)
+        .await
+        .map_err(|err| err.to_string())?;
+    info!("push connected to {}", server.ws_url());
+    let _ = events.send(Event::PushConnected);
+
+    while let Some(frame) = socket.next().await {
+        match frame.map_err(|err| err.to_string())? {
+            WsMessage::Text(text) => match serde_json::from_str::<Push>(&text) {
+                Ok(Push::Message(message)) => {
+                    let _ = events.send(Event::NewMessage(message));
+                }
+                Ok(Push::DirectMessage(message)) => {
+                    let _ = events.send(Event::NewDirectMessage(message));
+                }
+                Ok(Push::FriendsChanged) => {
+                    let _ = events.send(Event::FriendsChanged);
+                }
+                Ok(Push::RoomsChanged) => {
+                    let _ = events.send(Event::RoomsChanged);
+                }
+                // Ignore frames from newer servers we don't understand
+                Err(err) => debug!("ignored push frame ({err}): {text}"),
+            },
+            WsMessage::Close(_) => break,
+            _ => {}
+        }
+    }
+
+    Err("connection closed".to_string())
+}